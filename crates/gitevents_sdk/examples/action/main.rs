@@ -5,7 +5,7 @@ use async_trait::async_trait;
 use gitevents_sdk::{
     cron::SchedulerOpts,
     events::{EventHandler, EventRequest, EventResponse},
-    git::{simulated::GitSimulated, GitEvent},
+    git::{simulated::GitSimulated, CommitEvent, GitEvent},
 };
 use tokio::sync::Mutex;
 use tracing::Level;
@@ -18,15 +18,21 @@ async fn main() -> eyre::Result<()> {
         .init();
 
     let simulated_git = GitSimulated::new()
-        .insert(GitEvent {
+        .insert(GitEvent::Commit(CommitEvent {
             commit: "something1".into(),
-        })
-        .insert(GitEvent {
+            reference: "refs/heads/main".into(),
+            ..Default::default()
+        }))
+        .insert(GitEvent::Commit(CommitEvent {
             commit: "something2".into(),
-        })
-        .insert(GitEvent {
+            reference: "refs/heads/main".into(),
+            ..Default::default()
+        }))
+        .insert(GitEvent::Commit(CommitEvent {
             commit: "something3".into(),
-        });
+            reference: "refs/heads/main".into(),
+            ..Default::default()
+        }));
 
     gitevents_sdk::builder::Builder::new()
         .add_git_provider(Arc::new(Mutex::new(simulated_git)))
@@ -34,7 +40,7 @@ async fn main() -> eyre::Result<()> {
             // Duration must not be lower than 1 second, otherwise async runtime won't proceed
             duration: Duration::from_secs(1),
         })
-        .action(|_req| async move { Ok(EventResponse {}) })
+        .action(|_req| async move { Ok(EventResponse::Ack) })
         .action(other_action)
         .add_handler(Arc::new(TestHandler {}))
         .execute()
@@ -44,7 +50,7 @@ async fn main() -> eyre::Result<()> {
 }
 
 async fn other_action(_req: EventRequest) -> eyre::Result<EventResponse> {
-    Ok(EventResponse {})
+    Ok(EventResponse::Ack)
 }
 
 pub struct TestHandler;
@@ -52,6 +58,6 @@ pub struct TestHandler;
 #[async_trait]
 impl EventHandler for TestHandler {
     async fn handle(&self, _req: EventRequest) -> eyre::Result<EventResponse> {
-        Ok(EventResponse {})
+        Ok(EventResponse::Ack)
     }
 }
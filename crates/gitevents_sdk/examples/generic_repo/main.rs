@@ -5,6 +5,7 @@ use async_trait::async_trait;
 use gitevents_sdk::{
     cron::SchedulerOpts,
     events::{EventHandler, EventRequest, EventResponse},
+    git::Credentials,
 };
 use tracing::Level;
 
@@ -17,12 +18,14 @@ async fn main() -> eyre::Result<()> {
 
     gitevents_sdk::builder::Builder::new()
         .set_generic_git_url("git@git.front.kjuulh.io:kjuulh/gitevents.git")
+        .set_git_credentials(Credentials::from_env().unwrap_or_default())
         .set_scheduler_opts(&SchedulerOpts {
             // Duration must not be lower than 1 second, otherwise async runtime won't proceed
             duration: Duration::from_secs(10),
         })
-        .action(|_req| async move { Ok(EventResponse {}) })
+        .action(|_req| async move { Ok(EventResponse::Ack) })
         .action(other_action)
+        .on_paths(["crates/**", "Cargo.toml"])
         .add_handler(Arc::new(TestHandler {}))
         .execute()
         .await?;
@@ -31,7 +34,7 @@ async fn main() -> eyre::Result<()> {
 }
 
 async fn other_action(_req: EventRequest) -> eyre::Result<EventResponse> {
-    Ok(EventResponse {})
+    Ok(EventResponse::Ack)
 }
 
 pub struct TestHandler;
@@ -39,6 +42,6 @@ pub struct TestHandler;
 #[async_trait]
 impl EventHandler for TestHandler {
     async fn handle(&self, _req: EventRequest) -> eyre::Result<EventResponse> {
-        Ok(EventResponse {})
+        Ok(EventResponse::Ack)
     }
 }
@@ -1,37 +1,97 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use futures::{Future, FutureExt};
 use tokio::sync::Mutex;
 
 use crate::action_event_handler::ActionEventHandler;
-use crate::cron::{CronExecutor, SchedulerOpts};
-use crate::events::{ActionFunc, EventHandler, EventRequest, EventResponse};
-use crate::git::generic::GitGeneric;
-use crate::git::GitProvider;
+use crate::cron::{CronExecutor, ErrorFunc, ExecutorError, SchedulerOpts};
+use crate::events::{ActionFunc, EventHandler, EventRequest, EventResponse, HandlerRegistration};
+use crate::filter::{EventFilter, PathFilter};
+use crate::git::generic::{GitGeneric, GitGenericOpts};
+use crate::git::{Credentials, GitProvider};
+use crate::progress::DynProgressStore;
+use crate::retry::{DeadLetterHandler, RetryPolicy};
+#[cfg(feature = "nats")]
+use crate::sinks::nats::NatsHandler;
+#[cfg(feature = "webhook")]
+use crate::sinks::webhook::WebhookHandler;
+use crate::storage::persistent::PersistentStorage;
+use crate::storage::DynStorage;
 
 #[allow(dead_code)]
 pub struct Builder {
+    generic_git_urls: Vec<String>,
+    generic_git_opts: GitGenericOpts,
+    progress_store: Option<DynProgressStore>,
+    storage: Option<DynStorage>,
     git_providers: Vec<Arc<Mutex<dyn GitProvider + Send + Sync>>>,
-    handlers: HashMap<uuid::Uuid, Arc<dyn EventHandler + Send + Sync>>,
+    handlers: HashMap<uuid::Uuid, HandlerRegistration>,
+    /// The handler which filters are added to.
+    last_handler: Option<uuid::Uuid>,
     scheduler_opts: SchedulerOpts,
+    on_error: Option<ErrorFunc>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Builder {
     pub fn new() -> Self {
         Self {
+            generic_git_urls: Default::default(),
+            generic_git_opts: Default::default(),
+            progress_store: None,
+            storage: None,
             git_providers: Default::default(),
             handlers: HashMap::new(),
+            last_handler: None,
             scheduler_opts: Default::default(),
+            on_error: None,
         }
     }
 
     pub fn set_generic_git_url(mut self, url: impl Into<String>) -> Self {
-        self.git_providers
-            .push(Arc::new(Mutex::new(GitGeneric::new(url))));
+        self.generic_git_urls.push(url.into());
+        self
+    }
+
+    /// Options applied to every repository added through `set_generic_git_url`.
+    pub fn set_generic_git_opts(mut self, opts: &GitGenericOpts) -> Self {
+        self.generic_git_opts = opts.clone();
+        self
+    }
+
+    /// Credentials used by every repository added through `set_generic_git_url`.
+    pub fn set_git_credentials(mut self, credentials: Credentials) -> Self {
+        self.generic_git_opts.credentials = credentials;
+        self
+    }
+
+    /// Where every repository added through `set_generic_git_url` persists its progress, see
+    /// [`GitGeneric::set_progress_store`]. Progress is kept in memory by default.
+    pub fn set_progress_store(mut self, store: DynProgressStore) -> Self {
+        self.progress_store = Some(store);
         self
     }
 
+    /// Where every repository added through `set_generic_git_url` is cloned to. Each repository
+    /// gets its own temporary directory by default.
+    pub fn set_storage(mut self, storage: DynStorage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Clone every repository added through `set_generic_git_url` below `dir`, and reuse the
+    /// clones on restart, see [`PersistentStorage`].
+    pub fn set_storage_dir(self, dir: impl Into<PathBuf>) -> Self {
+        self.set_storage(Arc::new(PersistentStorage::new(dir)))
+    }
+
     pub fn add_git_provider(
         mut self,
         git_provider: Arc<Mutex<dyn GitProvider + Send + Sync>>,
@@ -45,34 +105,128 @@ impl Builder {
         self
     }
 
-    pub fn action<F, Fut>(mut self, func: F) -> Self
+    /// Called for every failing git provider and handler, the executor keeps running either way.
+    pub fn on_error(mut self, on_error: impl Fn(&ExecutorError) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Arc::new(on_error));
+        self
+    }
+
+    pub fn action<F, Fut>(self, func: F) -> Self
     where
         F: Send + Sync + 'static,
         F: Fn(EventRequest) -> Fut,
         Fut: Send + 'static,
         Fut: Future<Output = eyre::Result<EventResponse>>,
     {
-        self.handlers.insert(
-            uuid::Uuid::new_v4(),
-            Arc::new(ActionEventHandler::new(Arc::new(convert(func)))),
-        );
-        self
+        self.add_handler(Arc::new(ActionEventHandler::new(Arc::new(convert(func)))))
     }
 
     pub fn add_handler(mut self, handler: Arc<dyn EventHandler + Send + Sync>) -> Self {
-        self.handlers.insert(uuid::Uuid::new_v4(), handler);
+        let id = uuid::Uuid::new_v4();
+        self.handlers.insert(id, HandlerRegistration::new(handler));
+        self.last_handler = Some(id);
         self
     }
 
-    pub async fn execute(self) -> eyre::Result<()> {
-        CronExecutor::new(self.scheduler_opts)
-            .run(&self.git_providers, &self.handlers)
-            .await?;
+    /// POSTs every event to `url`, see [`WebhookHandler`] to configure retries, headers and
+    /// signing.
+    ///
+    /// # Panics
+    ///
+    /// If the HTTP client can't be created.
+    #[cfg(feature = "webhook")]
+    pub fn webhook(self, url: impl Into<String>) -> Self {
+        self.add_handler(Arc::new(
+            WebhookHandler::new(url).expect("failed to create the webhook client"),
+        ))
+    }
+
+    /// Publishes every event to the NATS server at `url`, see [`NatsHandler`] to configure the
+    /// subject and JetStream.
+    #[cfg(feature = "nats")]
+    pub fn nats(self, url: impl Into<String>) -> Self {
+        self.add_handler(Arc::new(NatsHandler::new(url)))
+    }
+
+    /// Only call the previously added handler for commits changing files matching one of
+    /// `patterns`, see [`PathFilter`] for the pattern syntax.
+    ///
+    /// # Panics
+    ///
+    /// If no handler has been added yet, or a pattern is invalid.
+    pub fn on_paths<I, S>(self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.with_filter(PathFilter::new(patterns).expect("invalid path pattern"))
+    }
+
+    /// Adds a filter to the previously added handler, it's only called for events matching all
+    /// of its filters.
+    ///
+    /// # Panics
+    ///
+    /// If no handler has been added yet.
+    pub fn with_filter(mut self, filter: impl EventFilter + Send + Sync + 'static) -> Self {
+        self.last_registration().filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Retries the previously added handler when it returns an error.
+    ///
+    /// # Panics
+    ///
+    /// If no handler has been added yet.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.last_registration().retry = policy;
+        self
+    }
+
+    /// Receives the events the previously added handler failed on after exhausting its retries,
+    /// or nacked.
+    ///
+    /// # Panics
+    ///
+    /// If no handler has been added yet.
+    pub fn with_dead_letter(
+        mut self,
+        dead_letter: impl DeadLetterHandler + Send + Sync + 'static,
+    ) -> Self {
+        self.last_registration().dead_letter = Some(Arc::new(dead_letter));
+        self
+    }
+
+    fn last_registration(&mut self) -> &mut HandlerRegistration {
+        let id = self
+            .last_handler
+            .expect("handler options have to be added after the handler they apply to");
+        self.handlers
+            .get_mut(&id)
+            .expect("last handler to be registered")
+    }
+
+    pub async fn execute(mut self) -> eyre::Result<()> {
+        for url in &self.generic_git_urls {
+            let mut git = GitGeneric::with_opts(url, &self.generic_git_opts)?;
+            if let Some(store) = &self.progress_store {
+                git = git.set_progress_store(store.clone());
+            }
+            if let Some(storage) = &self.storage {
+                git = git.set_storage(storage.clone());
+            }
+            self.git_providers.push(Arc::new(Mutex::new(git)));
+        }
+
+        let mut executor = CronExecutor::new(self.scheduler_opts);
+        if let Some(on_error) = self.on_error {
+            executor = executor.set_on_error(on_error);
+        }
+        executor.run(&self.git_providers, &self.handlers).await?;
 
-        tokio::signal::ctrl_c().await.and_then(|_| {
+        tokio::signal::ctrl_c().await.map(|_| {
             println!();
             println!("received shutdown");
-            Ok(())
         })?;
 
         Ok(())
@@ -0,0 +1,256 @@
+//! Events as [CloudEvents 1.0](https://github.com/cloudevents/spec/blob/v1.0.2/cloudevents/spec.md).
+//!
+//! | event             | `type`                           |
+//! |-------------------|----------------------------------|
+//! | repository cloned | `io.gitevents.repository.cloned` |
+//! | commit            | `io.gitevents.commit.pushed`     |
+//! | tag created       | `io.gitevents.tag.created`       |
+//! | tag moved         | `io.gitevents.tag.moved`         |
+//! | tag deleted       | `io.gitevents.tag.deleted`       |
+//! | ref rewritten     | `io.gitevents.ref.rewritten`     |
+//! | branch created    | `io.gitevents.branch.created`    |
+//! | branch deleted    | `io.gitevents.branch.deleted`    |
+//!
+//! `source` is the repository url, with scp-like urls such as `git@github.com:org/repo.git`
+//! written as `ssh://git@github.com/org/repo.git` to make them a URI-reference, and `subject`
+//! the full ref name. `data` holds the event in the
+//! JSON schema of [`crate::envelope`], whose version is carried in the `schemaversion` extension
+//! attribute.
+//!
+//! The `id` is derived from the type, ref and commit, and the previous commit for moved tags and
+//! rewritten refs, the same as the id of the [`crate::envelope::Envelope`] of the event. An event
+//! which is emitted again, e.g. after a restart, keeps its id and can be deduplicated by the
+//! consumer. Events only carry the state of a ref, not how often it reached
+//! that state, so a genuinely new occurrence of the same change shares the id of the first one:
+//! a tag deleted and created again on the same commit, or a branch deleted and created again at
+//! the same tip. Likewise every clone of a repository, e.g. after a restart without persistent
+//! storage, reports `io.gitevents.repository.cloned` with the same id. Consumers which can't
+//! tolerate dropping those shouldn't deduplicate these events by id alone.
+
+use serde::{Deserialize, Serialize};
+
+use crate::envelope::{event_id, event_type, SCHEMA_VERSION};
+use crate::git::GitEvent;
+
+const SPEC_VERSION: &str = "1.0";
+const DATA_CONTENT_TYPE: &str = "application/json";
+const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloudEvent {
+    pub specversion: String,
+    pub id: String,
+    pub source: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    pub datacontenttype: String,
+    /// Extension attribute, the [`SCHEMA_VERSION`] of `data`.
+    pub schemaversion: u32,
+    pub data: serde_json::Value,
+}
+
+/// A CloudEvent encoded for an HTTP request, or any other transport with headers and a body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpMessage {
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// How a CloudEvent is laid out in an [`HttpMessage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// The whole event as the JSON body, with `content-type: application/cloudevents+json`.
+    #[default]
+    Structured,
+    /// The attributes as `ce-` headers, and only `data` as the body.
+    Binary,
+}
+
+impl CloudEvent {
+    pub fn new(event: &GitEvent) -> eyre::Result<Self> {
+        let ty = event_type(event);
+        let subject = event.reference();
+
+        Ok(Self {
+            specversion: SPEC_VERSION.into(),
+            id: event_id(event),
+            source: source(event.url()),
+            ty: ty.into(),
+            subject,
+            datacontenttype: DATA_CONTENT_TYPE.into(),
+            schemaversion: SCHEMA_VERSION,
+            data: serde_json::to_value(event)?,
+        })
+    }
+
+    pub fn to_http(&self, mode: Mode) -> eyre::Result<HttpMessage> {
+        match mode {
+            Mode::Structured => Ok(HttpMessage {
+                headers: vec![("content-type".into(), STRUCTURED_CONTENT_TYPE.into())],
+                body: serde_json::to_vec(self)?,
+            }),
+            Mode::Binary => {
+                let mut headers = vec![
+                    ("ce-specversion".into(), encode_header(&self.specversion)),
+                    ("ce-id".into(), encode_header(&self.id)),
+                    ("ce-source".into(), encode_header(&self.source)),
+                    ("ce-type".into(), encode_header(&self.ty)),
+                ];
+                if let Some(subject) = &self.subject {
+                    headers.push(("ce-subject".into(), encode_header(subject)));
+                }
+                headers.push(("ce-schemaversion".into(), self.schemaversion.to_string()));
+                headers.push(("content-type".into(), self.datacontenttype.clone()));
+
+                Ok(HttpMessage {
+                    headers,
+                    body: serde_json::to_vec(&self.data)?,
+                })
+            }
+        }
+    }
+}
+
+/// Rewrites scp-like urls, `[user@]host:path`, to the equivalent `ssh://` url. Anything else is
+/// already a URI-reference, either a url with a scheme or a local path.
+fn source(url: &str) -> String {
+    if url.contains("://") {
+        return url.into();
+    }
+
+    match url.split_once(':') {
+        // A single letter is a Windows drive rather than a host
+        Some((host, path)) if host.len() > 1 && !host.contains('/') => {
+            format!("ssh://{}/{}", host, path.trim_start_matches('/'))
+        }
+        _ => url.into(),
+    }
+}
+
+/// Percent-encodes what the HTTP binding doesn't allow in `ce-` header values: spaces, double
+/// quotes, percent signs, and anything outside printable ASCII.
+fn encode_header(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_graphic() && c != '"' && c != '%' {
+            encoded.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", b));
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::{CommitEvent, GitEvent, TagChange, TagEvent};
+
+    use super::{source, CloudEvent, Mode};
+
+    fn commit(commit: &str) -> GitEvent {
+        GitEvent::Commit(CommitEvent {
+            commit: commit.into(),
+            reference: "refs/heads/main".into(),
+            url: "https://example.com/repo.git".into(),
+            message: "feat: ünïcode".into(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_attributes_and_deterministic_id() {
+        let event = CloudEvent::new(&commit("abc")).unwrap();
+
+        assert_eq!(event.ty, "io.gitevents.commit.pushed");
+        assert_eq!(event.source, "https://example.com/repo.git");
+        assert_eq!(event.subject.as_deref(), Some("refs/heads/main"));
+        assert_eq!(event.data["kind"], "commit");
+        assert_eq!(event.id, CloudEvent::new(&commit("abc")).unwrap().id);
+        assert_ne!(event.id, CloudEvent::new(&commit("def")).unwrap().id);
+    }
+
+    #[test]
+    fn test_id_of_moves_depends_on_previous_commit() {
+        let moved = |previous: &str| {
+            GitEvent::Tag(TagEvent {
+                name: "latest".into(),
+                url: "https://example.com/repo.git".into(),
+                change: TagChange::Moved {
+                    previous: previous.into(),
+                },
+                commit: "abc".into(),
+                message: None,
+                path: Default::default(),
+            })
+        };
+
+        let event = CloudEvent::new(&moved("def")).unwrap();
+        assert_eq!(event.id, CloudEvent::new(&moved("def")).unwrap().id);
+        assert_ne!(event.id, CloudEvent::new(&moved("ghi")).unwrap().id);
+    }
+
+    #[test]
+    fn test_source_is_a_uri_reference() {
+        assert_eq!(
+            source("git@github.com:kjuulh/gitevents.git"),
+            "ssh://git@github.com/kjuulh/gitevents.git"
+        );
+        assert_eq!(
+            source("https://github.com/kjuulh/gitevents.git"),
+            "https://github.com/kjuulh/gitevents.git"
+        );
+        assert_eq!(
+            source("ssh://git@github.com:22/kjuulh/gitevents.git"),
+            "ssh://git@github.com:22/kjuulh/gitevents.git"
+        );
+        assert_eq!(source("/srv/git/repo.git"), "/srv/git/repo.git");
+    }
+
+    #[test]
+    fn test_structured_mode() {
+        let event = CloudEvent::new(&commit("abc")).unwrap();
+        let message = event.to_http(Mode::Structured).unwrap();
+
+        assert_eq!(
+            message.headers,
+            vec![(
+                "content-type".to_string(),
+                "application/cloudevents+json".to_string()
+            )]
+        );
+        let body: serde_json::Value = serde_json::from_slice(&message.body).unwrap();
+        assert_eq!(body["specversion"], "1.0");
+        assert_eq!(body["type"], "io.gitevents.commit.pushed");
+        assert_eq!(serde_json::from_value::<CloudEvent>(body).unwrap(), event);
+    }
+
+    #[test]
+    fn test_binary_mode() {
+        let mut event = CloudEvent::new(&commit("abc")).unwrap();
+        event.subject = Some("refs/heads/\"ü\" 100%".into());
+        let message = event.to_http(Mode::Binary).unwrap();
+
+        let header = |name: &str| {
+            message
+                .headers
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(header("ce-type"), Some("io.gitevents.commit.pushed"));
+        assert_eq!(header("ce-id"), Some(event.id.as_str()));
+        assert_eq!(
+            header("ce-subject"),
+            Some("refs/heads/%22%C3%BC%22%20100%25")
+        );
+        assert_eq!(header("content-type"), Some("application/json"));
+
+        let body: serde_json::Value = serde_json::from_slice(&message.body).unwrap();
+        assert_eq!(body, event.data);
+    }
+}
@@ -1,13 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::time::Duration;
 
+use futures::{Future, FutureExt};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::Mutex;
 use tokio::task::JoinSet;
 use tokio_cron_scheduler::{Job, JobScheduler};
 
-use crate::events::{EventHandler, EventRequest};
+use crate::events::{EventRequest, EventResponse, HandlerRegistration};
 use crate::git::{GitEvent, GitProvider};
+use crate::retry::DeadLetter;
+
+/// How often a handler may ask for the same event to be redelivered with
+/// [`EventResponse::RetryAfter`] before it's given up on.
+const MAX_REDELIVERIES: usize = 10;
 
 #[derive(Clone, Debug)]
 pub struct SchedulerOpts {
@@ -22,100 +31,702 @@ impl Default for SchedulerOpts {
     }
 }
 
-#[derive(Default, Clone, Debug)]
+/// A failure the executor recovered from, reported to the callback set with
+/// [`CronExecutor::set_on_error`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ExecutorError {
+    /// Listening to a git provider failed, it's polled again on the next run, or telling it which
+    /// events were processed with [`GitProvider::processed`] failed. `provider` is its position
+    /// in the list of providers.
+    Provider {
+        provider: usize,
+        error: eyre::Report,
+    },
+    /// A handler failed on an event, after exhausting its retries. The event is dead-lettered
+    /// afterwards.
+    Handler(Box<DeadLetter>),
+}
+
+impl fmt::Display for ExecutorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Provider { provider, error } => {
+                write!(f, "git provider {} failed: {:#}", provider, error)
+            }
+            Self::Handler(letter) => write!(
+                f,
+                "handler {} failed after {} attempt(s): {:#}",
+                letter.handler, letter.attempts, letter.error
+            ),
+        }
+    }
+}
+
+pub type ErrorFunc = Arc<dyn Fn(&ExecutorError) + Send + Sync>;
+
+#[derive(Default, Clone)]
 pub struct CronExecutor {
     opts: SchedulerOpts,
+    on_error: Option<ErrorFunc>,
+}
+
+impl fmt::Debug for CronExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CronExecutor")
+            .field("opts", &self.opts)
+            .finish_non_exhaustive()
+    }
 }
 
 impl CronExecutor {
     pub fn new(opts: SchedulerOpts) -> Self {
-        Self { opts }
+        Self {
+            opts,
+            on_error: None,
+        }
+    }
+
+    /// Called for every provider and handler failure, after it's been logged.
+    pub fn set_on_error(mut self, on_error: ErrorFunc) -> Self {
+        self.on_error = Some(on_error);
+        self
     }
 
     pub async fn run(
         &self,
-        git_providers: &Vec<Arc<Mutex<dyn GitProvider + Send + Sync>>>,
-        handlers: &HashMap<uuid::Uuid, Arc<dyn EventHandler + Send + Sync>>,
+        git_providers: &[Arc<Mutex<dyn GitProvider + Send + Sync>>],
+        handlers: &HashMap<uuid::Uuid, HandlerRegistration>,
     ) -> eyre::Result<()> {
         let sched = JobScheduler::new().await?;
 
-        let git_providers = git_providers.clone();
+        let git_providers = git_providers.to_vec();
+
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Batch>();
+        let (done_tx, done_rx) = tokio::sync::mpsc::unbounded_channel::<(u64, Batch)>();
 
-        let (tx, mut rx) = tokio::sync::broadcast::channel::<GitEvent>(2);
+        tokio::spawn(acknowledge(
+            git_providers.clone(),
+            done_rx,
+            self.on_error.clone(),
+        ));
 
-        let mut clone_js: JoinSet<eyre::Result<()>> = JoinSet::new();
+        poll(&git_providers, &tx, &self.on_error).await;
 
-        for provider in git_providers.clone() {
+        let on_error = self.on_error.clone();
+        let job = Job::new_repeated_async(self.opts.duration, move |uuid, _l| {
+            let git_providers = git_providers.clone();
             let tx = tx.clone();
+            let on_error = on_error.clone();
+            Box::pin(async move {
+                tracing::trace!(uuid = uuid.to_string(), "executing job");
+                poll(&git_providers, &tx, &on_error).await;
+            })
+        })?;
 
-            clone_js.spawn(async move {
-                tracing::trace!("syncing git_provider");
-                if let Some(event) = provider.lock().await.listen().await? {
-                    tx.send(event).unwrap();
-                }
+        tokio::spawn(dispatch(
+            handlers.clone(),
+            rx,
+            done_tx,
+            self.on_error.clone(),
+        ));
+
+        sched.shutdown_on_ctrl_c();
+
+        sched.add(job).await?;
+        sched.start().await?;
 
-                Ok(())
-            });
+        Ok(())
+    }
+}
+
+fn report(on_error: &Option<ErrorFunc>, err: &ExecutorError) {
+    tracing::error!(error = err.to_string(), "recovered from failure");
+    if let Some(on_error) = on_error {
+        on_error(err);
+    }
+}
+
+/// The events of a single successful `listen` of a provider, possibly none.
+struct Batch {
+    /// The position of the provider in the list of providers.
+    provider: usize,
+    events: Vec<GitEvent>,
+}
+
+/// A batch the handlers are working on. Once they're all done with its events, and the last
+/// reference is dropped, it's sent to [`acknowledge`] with its position among the batches of
+/// its provider.
+struct InFlight {
+    seq: u64,
+    batch: Option<Batch>,
+    done: UnboundedSender<(u64, Batch)>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(batch) = self.batch.take() {
+            // Only fails once the executor is shutting down.
+            let _ = self.done.send((self.seq, batch));
         }
+    }
+}
+
+/// Listens to every provider concurrently, and sends their events to the dispatcher. A failing
+/// provider is reported, and doesn't affect the others.
+async fn poll(
+    git_providers: &[Arc<Mutex<dyn GitProvider + Send + Sync>>],
+    tx: &UnboundedSender<Batch>,
+    on_error: &Option<ErrorFunc>,
+) {
+    let mut js = JoinSet::new();
+
+    for (index, provider) in git_providers.iter().cloned().enumerate() {
+        let tx = tx.clone();
+
+        js.spawn(async move {
+            tracing::trace!(provider = index, "syncing git_provider");
+            let res = catch_panic(async {
+                // Sent while still holding the lock, so batches arrive in the order they were
+                // listened to.
+                let mut provider = provider.lock().await;
+                let events = provider.listen().await?;
+                tx.send(Batch {
+                    provider: index,
+                    events,
+                })
+                .map_err(|_| eyre::eyre!("the event dispatcher has stopped"))
+            })
+            .await;
 
-        while let Some(task) = clone_js.join_next().await {
-            task.unwrap().unwrap();
+            (index, res)
+        });
+    }
+
+    while let Some(task) = js.join_next().await {
+        match task {
+            Ok((_, Ok(()))) => {}
+            Ok((provider, Err(error))) => {
+                report(on_error, &ExecutorError::Provider { provider, error })
+            }
+            Err(e) => tracing::error!(error = e.to_string(), "git provider was cancelled"),
         }
+    }
+}
 
-        let job = Job::new_repeated_async(self.opts.duration, move |uuid, _l| {
-            let git_providers = git_providers.clone();
-            let tx = tx.clone();
-            Box::pin(async move {
-                tracing::trace!(uuid = uuid.to_string(), "executing job");
+/// Turns a panic into an error, so a misbehaving provider or handler is treated like a failing
+/// one.
+async fn catch_panic<T>(fut: impl Future<Output = eyre::Result<T>>) -> eyre::Result<T> {
+    match AssertUnwindSafe(fut).catch_unwind().await {
+        Ok(res) => res,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(eyre::eyre!("panicked: {}", message))
+        }
+    }
+}
 
-                let mut clone_js: JoinSet<eyre::Result<()>> = JoinSet::new();
+/// Delivers every received event to the handlers whose filters match it, until `rx` is closed and
+/// the handlers are done with the events queued for them. Every batch is sent to `done` once the
+/// handlers are done with all of its events.
+///
+/// Every handler has its own queue, which is worked through in order by its own task. A handler
+/// retrying an event or waiting to have it redelivered only holds up its own later events.
+async fn dispatch(
+    handlers: HashMap<uuid::Uuid, HandlerRegistration>,
+    mut rx: UnboundedReceiver<Batch>,
+    done: UnboundedSender<(u64, Batch)>,
+    on_error: Option<ErrorFunc>,
+) {
+    let mut workers = JoinSet::new();
+    let mut queues = Vec::with_capacity(handlers.len());
 
-                for provider in git_providers {
-                    let tx = tx.clone();
+    for (uuid, registration) in handlers {
+        let (tx, mut queue) =
+            tokio::sync::mpsc::unbounded_channel::<(EventRequest, Arc<InFlight>)>();
+        let worker = registration.clone();
+        let on_error = on_error.clone();
+        workers.spawn(async move {
+            // The batch is only released once the event is delivered.
+            while let Some((req, _batch)) = queue.recv().await {
+                deliver(uuid, &worker, req, &on_error).await;
+            }
+        });
+        queues.push((uuid, registration, tx));
+    }
 
-                    clone_js.spawn(async move {
-                        tracing::trace!("syncing git_provider");
-                        if let Some(event) = provider.lock().await.listen().await? {
-                            tx.send(event).unwrap();
-                        }
+    let mut seqs = HashMap::<usize, u64>::new();
+    while let Some(batch) = rx.recv().await {
+        let seq = seqs.entry(batch.provider).or_default();
+        let events = batch.events.clone();
+        let in_flight = Arc::new(InFlight {
+            seq: *seq,
+            batch: Some(batch),
+            done: done.clone(),
+        });
+        *seq += 1;
 
-                        Ok(())
-                    });
+        for event in events {
+            for (uuid, registration, tx) in &queues {
+                let req = EventRequest { git: event.clone() };
+                if !registration.matches(&req) {
+                    tracing::trace!(uuid = uuid.to_string(), "event filtered out");
+                    continue;
                 }
 
-                while let Some(task) = clone_js.join_next().await {
-                    task.unwrap().unwrap();
+                if tx.send((req, in_flight.clone())).is_err() {
+                    tracing::error!(uuid = uuid.to_string(), "handler has stopped");
                 }
-            })
-        })?;
+            }
+        }
+    }
 
-        let handlers = handlers.clone();
-        tokio::spawn(async move {
-            while let Ok(event) = rx.recv().await {
-                let handlers = handlers.clone();
-                let mut js: JoinSet<eyre::Result<()>> = JoinSet::new();
+    drop(queues);
+    while let Some(task) = workers.join_next().await {
+        if let Err(e) = task {
+            tracing::error!(error = e.to_string(), "handler was cancelled");
+        }
+    }
+}
 
-                for (uuid, handler) in handlers {
-                    let event = event.clone();
-                    js.spawn(async move {
-                        tracing::info!(uuid = uuid.to_string(), "executing task");
-                        handler.handle(EventRequest { git: event }).await?;
+/// Tells the providers which of their batches the handlers are done with, see
+/// [`GitProvider::processed`]. Batches finished out of order are held back until the batches
+/// listened to before them are done too.
+async fn acknowledge(
+    git_providers: Vec<Arc<Mutex<dyn GitProvider + Send + Sync>>>,
+    mut done: UnboundedReceiver<(u64, Batch)>,
+    on_error: Option<ErrorFunc>,
+) {
+    let mut next = HashMap::<usize, u64>::new();
+    let mut finished = HashMap::<usize, BTreeMap<u64, Batch>>::new();
 
-                        Ok(())
-                    });
-                }
+    while let Some((seq, batch)) = done.recv().await {
+        let provider = batch.provider;
+        let finished = finished.entry(provider).or_default();
+        finished.insert(seq, batch);
+
+        let next = next.entry(provider).or_default();
+        while let Some(batch) = finished.remove(next) {
+            *next += 1;
+
+            let res = catch_panic(async {
+                git_providers[provider]
+                    .lock()
+                    .await
+                    .processed(&batch.events)
+                    .await
+            })
+            .await;
+            if let Err(error) = res {
+                report(&on_error, &ExecutorError::Provider { provider, error });
+            }
+        }
+    }
+}
+
+/// Calls a single handler until it's done with the event, retrying it according to its policy,
+/// and dead-lettering the event if it gives up.
+async fn deliver(
+    uuid: uuid::Uuid,
+    registration: &HandlerRegistration,
+    req: EventRequest,
+    on_error: &Option<ErrorFunc>,
+) {
+    let mut attempts = 0;
+    let mut failures = 0;
+    let mut redeliveries = 0;
 
-                while let Some(task) = js.join_next().await {
-                    task.unwrap().unwrap();
+    let error = loop {
+        attempts += 1;
+        tracing::info!(uuid = uuid.to_string(), "executing task");
+        match catch_panic(registration.handler.handle(req.clone())).await {
+            Ok(EventResponse::Ack) => {
+                tracing::trace!(uuid = uuid.to_string(), "event acked");
+                return;
+            }
+            Ok(EventResponse::Skip) => {
+                tracing::debug!(uuid = uuid.to_string(), "event skipped");
+                return;
+            }
+            Ok(EventResponse::Nack) => break eyre::eyre!("event nacked by handler"),
+            Ok(EventResponse::RetryAfter(_)) if redeliveries >= MAX_REDELIVERIES => {
+                break eyre::eyre!("gave up on event after {} redeliveries", redeliveries)
+            }
+            Ok(EventResponse::RetryAfter(delay)) => {
+                tracing::debug!(uuid = uuid.to_string(), "redelivering event in {:?}", delay);
+                tokio::time::sleep(delay).await;
+                redeliveries += 1;
+            }
+            Err(e) => {
+                failures += 1;
+                if failures >= registration.retry.max_attempts {
+                    break e;
                 }
+
+                let delay = registration.retry.delay(failures);
+                tracing::warn!(
+                    uuid = uuid.to_string(),
+                    attempt = failures,
+                    error = format!("{:#}", e),
+                    "handler failed, retrying in {:?}",
+                    delay
+                );
+                tokio::time::sleep(delay).await;
             }
+        }
+    };
+
+    let err = ExecutorError::Handler(Box::new(DeadLetter {
+        handler: uuid,
+        req,
+        attempts,
+        error,
+    }));
+    report(on_error, &err);
+
+    if let (Some(dead_letter), ExecutorError::Handler(letter)) = (&registration.dead_letter, err) {
+        if let Err(e) = catch_panic(dead_letter.handle(*letter)).await {
+            tracing::error!(
+                uuid = uuid.to_string(),
+                error = format!("{:#}", e),
+                "dead letter handler failed"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use crate::events::{EventHandler, EventRequest, EventResponse, HandlerRegistration};
+    use crate::git::simulated::GitSimulated;
+    use crate::git::{CommitEvent, GitEvent, GitProvider};
+    use crate::retry::{DeadLetter, RetryPolicy};
+
+    use super::{acknowledge, dispatch, poll, Batch, ErrorFunc, ExecutorError, MAX_REDELIVERIES};
+
+    /// Fails the first `failures` calls, then answers with `responses` in order, and acks after
+    /// that.
+    struct Handler {
+        failures: usize,
+        responses: Vec<EventResponse>,
+        calls: AtomicUsize,
+    }
+
+    impl Handler {
+        fn new(responses: &[EventResponse]) -> Arc<Self> {
+            Self::failing(0, responses)
+        }
+
+        fn failing(failures: usize, responses: &[EventResponse]) -> Arc<Self> {
+            Arc::new(Self {
+                failures,
+                responses: responses.to_vec(),
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl EventHandler for Handler {
+        async fn handle(&self, _req: EventRequest) -> eyre::Result<EventResponse> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures {
+                return Err(eyre::eyre!("connection refused").wrap_err("failed to handle"));
+            }
+
+            Ok(self
+                .responses
+                .get(call - self.failures)
+                .copied()
+                .unwrap_or(EventResponse::Ack))
+        }
+    }
+
+    fn handlers(handlers: &[&Arc<Handler>]) -> HashMap<uuid::Uuid, HandlerRegistration> {
+        handlers
+            .iter()
+            .map(|h| (uuid::Uuid::new_v4(), HandlerRegistration::new((*h).clone())))
+            .collect()
+    }
+
+    /// Dispatches `events` and waits for the handlers to be done with them.
+    async fn dispatch_events(
+        handlers: &HashMap<uuid::Uuid, HandlerRegistration>,
+        events: &[GitEvent],
+        on_error: &Option<ErrorFunc>,
+    ) {
+        let (tx, rx) = unbounded_channel();
+        tx.send(batch(events)).unwrap();
+        drop(tx);
+
+        let (done, _) = unbounded_channel();
+        dispatch(handlers.clone(), rx, done, on_error.clone()).await;
+    }
+
+    fn event() -> GitEvent {
+        GitEvent::Commit(CommitEvent::default())
+    }
+
+    fn batch(events: &[GitEvent]) -> Batch {
+        Batch {
+            provider: 0,
+            events: events.to_vec(),
+        }
+    }
+
+    fn retry(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    /// Registers `handler` with a retry policy, and collects what it dead-letters.
+    fn with_dead_letter(
+        handler: &Arc<Handler>,
+        retry: RetryPolicy,
+    ) -> (
+        HashMap<uuid::Uuid, HandlerRegistration>,
+        Arc<Mutex<Vec<DeadLetter>>>,
+    ) {
+        let letters: Arc<Mutex<Vec<DeadLetter>>> = Default::default();
+
+        let mut handlers = handlers(&[handler]);
+        for registration in handlers.values_mut() {
+            let letters = letters.clone();
+            registration.retry = retry.clone();
+            registration.dead_letter = Some(Arc::new(move |letter| {
+                let letters = letters.clone();
+                async move {
+                    letters.lock().unwrap().push(letter);
+                    Ok(())
+                }
+            }));
+        }
+
+        (handlers, letters)
+    }
+
+    #[tokio::test]
+    async fn test_responses_apply_per_handler() {
+        let retry = EventResponse::RetryAfter(Duration::from_millis(1));
+        let retrying = Handler::new(&[retry, retry]);
+        let nacking = Handler::new(&[EventResponse::Nack]);
+        let skipping = Handler::new(&[EventResponse::Skip]);
+
+        dispatch_events(
+            &handlers(&[&retrying, &nacking, &skipping]),
+            &[event()],
+            &None,
+        )
+        .await;
+
+        assert_eq!(retrying.calls(), 3);
+        assert_eq!(nacking.calls(), 1);
+        assert_eq!(skipping.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_redeliveries_only_hold_up_their_own_handler() {
+        let waiting = Handler::new(&[EventResponse::RetryAfter(Duration::from_millis(500))]);
+        let other = Handler::new(&[]);
+
+        let (tx, rx) = unbounded_channel();
+        tx.send(batch(&[event(), event()])).unwrap();
+        let (done, _) = unbounded_channel();
+        let dispatcher = tokio::spawn(dispatch(handlers(&[&waiting, &other]), rx, done, None));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(waiting.calls(), 1);
+        assert_eq!(other.calls(), 2);
+
+        drop(tx);
+        dispatcher.await.unwrap();
+        assert_eq!(waiting.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_redeliveries_are_bounded() {
+        let retry = EventResponse::RetryAfter(Duration::from_millis(1));
+        let retrying = Handler::new(&[retry; MAX_REDELIVERIES + 5]);
+
+        dispatch_events(&handlers(&[&retrying]), &[event()], &None).await;
+
+        assert_eq!(retrying.calls(), MAX_REDELIVERIES + 1);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_retried() {
+        let flaky = Handler::failing(2, &[]);
+        let (handlers, letters) = with_dead_letter(&flaky, retry(3));
+
+        dispatch_events(&handlers, &[event()], &None).await;
+
+        assert_eq!(flaky.calls(), 3);
+        assert!(letters.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_retries_are_dead_lettered() {
+        let failing = Handler::failing(usize::MAX, &[]);
+        let healthy = Handler::new(&[]);
+        let (mut registrations, letters) = with_dead_letter(&failing, retry(2));
+        registrations.extend(handlers(&[&healthy]));
+
+        dispatch_events(&registrations, &[event()], &None).await;
+
+        assert_eq!(failing.calls(), 2);
+        assert_eq!(healthy.calls(), 1);
+        let letters = letters.lock().unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].attempts, 2);
+        assert_eq!(
+            letters[0]
+                .error
+                .chain()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>(),
+            vec!["failed to handle", "connection refused"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nacks_are_dead_lettered_without_retries() {
+        let nacking = Handler::new(&[EventResponse::Nack]);
+        let (handlers, letters) = with_dead_letter(&nacking, retry(3));
+
+        dispatch_events(&handlers, &[event()], &None).await;
+
+        assert_eq!(nacking.calls(), 1);
+        assert_eq!(letters.lock().unwrap().len(), 1);
+    }
+
+    /// Collects the errors reported to the callback, formatted.
+    fn on_error() -> (Option<ErrorFunc>, Arc<Mutex<Vec<String>>>) {
+        let errors: Arc<Mutex<Vec<String>>> = Default::default();
+        let reported = errors.clone();
+        let on_error: ErrorFunc = Arc::new(move |err: &ExecutorError| {
+            reported.lock().unwrap().push(err.to_string());
         });
+        (Some(on_error), errors)
+    }
 
-        sched.shutdown_on_ctrl_c();
+    struct FailingProvider {
+        panic: bool,
+    }
 
-        sched.add(job).await?;
-        sched.start().await?;
+    #[async_trait]
+    impl GitProvider for FailingProvider {
+        async fn listen(&mut self) -> eyre::Result<Vec<GitEvent>> {
+            if self.panic {
+                panic!("provider bug");
+            }
+            Err(eyre::eyre!("remote unreachable"))
+        }
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn test_failing_providers_are_isolated() {
+        let providers: Vec<Arc<tokio::sync::Mutex<dyn GitProvider + Send + Sync>>> = vec![
+            Arc::new(tokio::sync::Mutex::new(FailingProvider { panic: false })),
+            Arc::new(tokio::sync::Mutex::new(FailingProvider { panic: true })),
+            Arc::new(tokio::sync::Mutex::new(GitSimulated::new().insert(event()))),
+        ];
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let (on_error, errors) = on_error();
+
+        poll(&providers, &tx, &on_error).await;
+
+        let batch = rx.try_recv().unwrap();
+        assert_eq!(batch.provider, 2);
+        assert!(matches!(batch.events[..], [GitEvent::Commit(_)]));
+        assert!(rx.try_recv().is_err());
+        let mut errors = errors.lock().unwrap().clone();
+        errors.sort();
+        assert_eq!(
+            errors,
+            vec![
+                "git provider 0 failed: remote unreachable",
+                "git provider 1 failed: panicked: provider bug",
+            ]
+        );
+    }
+
+    /// Records how many events every call to `processed` was made with.
+    #[derive(Default)]
+    struct RecordingProvider {
+        processed: Arc<Mutex<Vec<usize>>>,
+    }
+
+    #[async_trait]
+    impl GitProvider for RecordingProvider {
+        async fn listen(&mut self) -> eyre::Result<Vec<GitEvent>> {
+            Ok(Vec::new())
+        }
+
+        async fn processed(&mut self, events: &[GitEvent]) -> eyre::Result<()> {
+            self.processed.lock().unwrap().push(events.len());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_providers_are_told_once_handlers_are_done() {
+        let provider = RecordingProvider::default();
+        let processed = provider.processed.clone();
+        let providers: Vec<Arc<tokio::sync::Mutex<dyn GitProvider + Send + Sync>>> =
+            vec![Arc::new(tokio::sync::Mutex::new(provider))];
+        let waiting = Handler::new(&[EventResponse::RetryAfter(Duration::from_millis(300))]);
+
+        let (tx, rx) = unbounded_channel();
+        let (done_tx, done_rx) = unbounded_channel();
+        let acknowledger = tokio::spawn(acknowledge(providers, done_rx, None));
+        let dispatcher = tokio::spawn(dispatch(handlers(&[&waiting]), rx, done_tx, None));
+        tx.send(batch(&[event()])).unwrap();
+        tx.send(batch(&[])).unwrap();
+
+        // The empty batch is done right away, but held back until the one before it is.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(waiting.calls(), 1);
+        assert!(processed.lock().unwrap().is_empty());
+
+        drop(tx);
+        dispatcher.await.unwrap();
+        acknowledger.await.unwrap();
+        assert_eq!(waiting.calls(), 2);
+        assert_eq!(*processed.lock().unwrap(), vec![1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_handler_failures_are_reported() {
+        let failing = Handler::failing(usize::MAX, &[]);
+        let healthy = Handler::new(&[]);
+        let (on_error, errors) = on_error();
+
+        dispatch_events(&handlers(&[&failing, &healthy]), &[event()], &on_error).await;
+
+        assert_eq!(healthy.calls(), 1);
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].ends_with("failed after 1 attempt(s): failed to handle: connection refused")
+        );
     }
 }
@@ -0,0 +1,164 @@
+//! The JSON format events are exchanged in with other services.
+//!
+//! Every event is wrapped in an [`Envelope`], which carries the version of the schema, so
+//! consumers can reject or adapt to payloads they don't understand:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "id": "5f0c7e36-2f4f-5b0a-9a3a-8d6c1a3c4d2e",
+//!   "event": {
+//!     "kind": "commit",
+//!     "commit": "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+//!     "reference": "refs/heads/main",
+//!     "url": "git@github.com:kjuulh/gitevents.git",
+//!     "author": { "name": "...", "email": "...", "time": 1677628800, "offset_minutes": 60 },
+//!     "committer": { "name": "...", "email": "...", "time": 1677628800, "offset_minutes": 60 },
+//!     "message": "feat: something\n",
+//!     "summary": "feat: something",
+//!     "parents": ["..."],
+//!     "changes": [
+//!       { "path": "src/lib.rs", "old_path": null, "kind": "modified", "insertions": 2, "deletions": 1 }
+//!     ],
+//!     "path": "/tmp/gitevents/storage/..."
+//!   }
+//! }
+//! ```
+//!
+//! `event.kind` is one of `repository`, `commit`, `tag`, `ref_rewritten`, `branch_created` or
+//! `branch_deleted`, and the remaining fields are those of the matching event struct in
+//! [`crate::git`]. Within a version fields are only ever added, consumers should ignore
+//! fields and kinds they don't know. Removing or changing a field bumps [`SCHEMA_VERSION`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::git::{GitEvent, RepositoryChange, TagChange, TagEvent};
+
+/// The version of the JSON schema produced by this release.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    /// The schema version the event was written with, see [`SCHEMA_VERSION`].
+    pub version: u32,
+    /// Derived from the event, so redeliveries and events emitted again after a restart keep
+    /// their id and can be deduplicated by consumers. The same as the id of the event as a
+    /// CloudEvent, which also documents when distinct events share an id.
+    pub id: String,
+    pub event: GitEvent,
+}
+
+impl Envelope {
+    pub fn new(event: GitEvent) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            id: event_id(&event),
+            event,
+        }
+    }
+}
+
+impl From<GitEvent> for Envelope {
+    fn from(event: GitEvent) -> Self {
+        Self::new(event)
+    }
+}
+
+/// A v5 uuid of the type, url, ref and commit of the event, and the previous commit of moved
+/// tags and rewritten refs.
+pub(crate) fn event_id(event: &GitEvent) -> String {
+    let previous = match event {
+        GitEvent::Tag(TagEvent {
+            change: TagChange::Moved { previous },
+            ..
+        }) => previous.as_str(),
+        GitEvent::RefRewritten(e) => &e.old,
+        _ => "",
+    };
+    let name = format!(
+        "{}\n{}\n{}\n{}\n{}",
+        event_type(event),
+        event.url(),
+        event.reference().unwrap_or_default(),
+        event.commit().unwrap_or_default(),
+        previous
+    );
+
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, name.as_bytes()).to_string()
+}
+
+/// The CloudEvents `type` of the event.
+pub(crate) fn event_type(event: &GitEvent) -> &'static str {
+    match event {
+        GitEvent::Repository(e) => match e.change {
+            RepositoryChange::Cloned => "io.gitevents.repository.cloned",
+        },
+        GitEvent::Commit(_) => "io.gitevents.commit.pushed",
+        GitEvent::Tag(e) => match e.change {
+            TagChange::Created => "io.gitevents.tag.created",
+            TagChange::Moved { .. } => "io.gitevents.tag.moved",
+            TagChange::Deleted => "io.gitevents.tag.deleted",
+        },
+        GitEvent::RefRewritten(_) => "io.gitevents.ref.rewritten",
+        GitEvent::BranchCreated(_) => "io.gitevents.branch.created",
+        GitEvent::BranchDeleted(_) => "io.gitevents.branch.deleted",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::{CommitEvent, GitEvent, TagChange, TagEvent};
+
+    use super::{Envelope, SCHEMA_VERSION};
+
+    #[test]
+    fn test_commit_round_trips() {
+        let envelope = Envelope::new(GitEvent::Commit(CommitEvent {
+            commit: "abc".into(),
+            reference: "refs/heads/main".into(),
+            ..Default::default()
+        }));
+
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["version"], SCHEMA_VERSION);
+        assert_eq!(json["event"]["kind"], "commit");
+        assert_eq!(json["event"]["reference"], "refs/heads/main");
+
+        let parsed: Envelope = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.id, envelope.id);
+        assert!(matches!(parsed.event, GitEvent::Commit(c) if c.commit == "abc"));
+    }
+
+    #[test]
+    fn test_id_is_derived_from_the_event() {
+        let commit = |commit: &str| {
+            Envelope::new(GitEvent::Commit(CommitEvent {
+                commit: commit.into(),
+                reference: "refs/heads/main".into(),
+                ..Default::default()
+            }))
+        };
+
+        assert_eq!(commit("abc").id, commit("abc").id);
+        assert_ne!(commit("abc").id, commit("def").id);
+    }
+
+    #[test]
+    fn test_tag_change_is_tagged() {
+        let envelope = Envelope::new(GitEvent::Tag(TagEvent {
+            name: "v1.0.0".into(),
+            url: "git@example.com:repo.git".into(),
+            change: TagChange::Moved {
+                previous: "abc".into(),
+            },
+            commit: "def".into(),
+            message: None,
+            path: Default::default(),
+        }));
+
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["event"]["kind"], "tag");
+        assert_eq!(json["event"]["change"]["type"], "moved");
+        assert_eq!(json["event"]["change"]["previous"], "abc");
+    }
+}
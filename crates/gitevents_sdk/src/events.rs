@@ -1,15 +1,34 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use async_trait::async_trait;
 use futures::future::BoxFuture;
 
+use crate::filter::DynEventFilter;
 use crate::git::GitEvent;
+use crate::retry::{DynDeadLetterHandler, RetryPolicy};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EventRequest {
     pub git: GitEvent,
 }
 
-#[derive(Debug, Clone)]
-pub struct EventResponse {}
+/// What a handler did with an event, decides whether the dispatcher delivers it to the handler
+/// again. Other handlers of the same event aren't affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum EventResponse {
+    /// The event was handled.
+    Ack,
+    /// The handler failed to process the event, it's dead-lettered without being retried.
+    Nack,
+    /// The handler can't process the event right now, redeliver it after the delay.
+    RetryAfter(Duration),
+    /// The handler isn't interested in the event.
+    Skip,
+}
 
 #[async_trait] // Will use this trait for now. async trait fns will probably be available soon'ish.
 pub trait EventHandler {
@@ -18,3 +37,29 @@ pub trait EventHandler {
 
 pub type ActionFunc =
     Box<dyn Send + Sync + Fn(EventRequest) -> BoxFuture<'static, eyre::Result<EventResponse>>>;
+
+/// A handler together with the filters deciding which events it receives.
+#[derive(Clone)]
+pub struct HandlerRegistration {
+    pub handler: Arc<dyn EventHandler + Send + Sync>,
+    /// All filters have to match for the handler to be called.
+    pub filters: Vec<DynEventFilter>,
+    pub retry: RetryPolicy,
+    /// Receives the events the handler gave up on, they're only logged without one.
+    pub dead_letter: Option<DynDeadLetterHandler>,
+}
+
+impl HandlerRegistration {
+    pub fn new(handler: Arc<dyn EventHandler + Send + Sync>) -> Self {
+        Self {
+            handler,
+            filters: Vec::new(),
+            retry: RetryPolicy::default(),
+            dead_letter: None,
+        }
+    }
+
+    pub fn matches(&self, req: &EventRequest) -> bool {
+        self.filters.iter().all(|f| f.matches(req))
+    }
+}
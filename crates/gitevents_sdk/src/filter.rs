@@ -0,0 +1,400 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use glob::{MatchOptions, Pattern};
+use regex::{Regex, RegexBuilder};
+
+use crate::events::EventRequest;
+use crate::git::{CommitEvent, GitEvent};
+
+/// Decides whether a handler should receive an event, evaluated before the handler is called.
+///
+/// Filters compose with [`EventFilter::and`], [`EventFilter::or`] and [`EventFilter::not`], e.g.
+/// `MessageFilter::new(r"\[skip ci\]")?.not().and(PathFilter::new(["src/**"])?)`.
+pub trait EventFilter {
+    fn matches(&self, req: &EventRequest) -> bool;
+
+    fn and<F: EventFilter>(self, other: F) -> And<Self, F>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    fn or<F: EventFilter>(self, other: F) -> Or<Self, F>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not(self)
+    }
+}
+
+pub type DynEventFilter = Arc<dyn EventFilter + Send + Sync>;
+
+#[derive(Clone, Debug)]
+pub struct And<A, B>(A, B);
+
+impl<A: EventFilter, B: EventFilter> EventFilter for And<A, B> {
+    fn matches(&self, req: &EventRequest) -> bool {
+        self.0.matches(req) && self.1.matches(req)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Or<A, B>(A, B);
+
+impl<A: EventFilter, B: EventFilter> EventFilter for Or<A, B> {
+    fn matches(&self, req: &EventRequest) -> bool {
+        self.0.matches(req) || self.1.matches(req)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Not<A>(A);
+
+impl<A: EventFilter> EventFilter for Not<A> {
+    fn matches(&self, req: &EventRequest) -> bool {
+        !self.0.matches(req)
+    }
+}
+
+impl<F: Fn(&EventRequest) -> bool> EventFilter for F {
+    fn matches(&self, req: &EventRequest) -> bool {
+        self(req)
+    }
+}
+
+fn commit(req: &EventRequest) -> Option<&CommitEvent> {
+    match &req.git {
+        GitEvent::Commit(commit) => Some(commit),
+        _ => None,
+    }
+}
+
+/// Matches commits changing files which match at least one `include` pattern, and no `exclude`
+/// pattern. Events which aren't commits never match.
+///
+/// Patterns containing a `/` are matched against the full path from the repository root, where
+/// `*` stays within a directory and `**` crosses them (`services/api/**`). Patterns without a
+/// `/` are matched against the file name alone (`*.proto`).
+#[derive(Clone, Debug, Default)]
+pub struct PathFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+const MATCH_OPTS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl PathFilter {
+    /// An empty `include` matches every changed file which isn't excluded.
+    pub fn new<I, S>(include: I) -> eyre::Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Ok(Self {
+            include: compile(include)?,
+            exclude: Vec::new(),
+        })
+    }
+
+    pub fn exclude<I, S>(mut self, exclude: I) -> eyre::Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.exclude.extend(compile(exclude)?);
+        Ok(self)
+    }
+
+    fn matches_path(&self, path: &Path) -> bool {
+        let matches = |pattern: &Pattern| {
+            if pattern.as_str().contains('/') {
+                pattern.matches_path_with(path, MATCH_OPTS)
+            } else {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .map(|name| pattern.matches_with(name, MATCH_OPTS))
+                    .unwrap_or(false)
+            }
+        };
+
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+impl EventFilter for PathFilter {
+    fn matches(&self, req: &EventRequest) -> bool {
+        let Some(commit) = commit(req) else {
+            return false;
+        };
+
+        commit.changes.iter().any(|change| {
+            self.matches_path(&change.path)
+                || change
+                    .old_path
+                    .as_ref()
+                    .map(|old| self.matches_path(old))
+                    .unwrap_or(false)
+        })
+    }
+}
+
+/// Matches commits whose full message matches a regex. Events which aren't commits never match.
+#[derive(Clone, Debug)]
+pub struct MessageFilter {
+    regex: Regex,
+}
+
+impl MessageFilter {
+    pub fn new(regex: &str) -> eyre::Result<Self> {
+        Ok(Self {
+            regex: Regex::new(regex)?,
+        })
+    }
+}
+
+impl EventFilter for MessageFilter {
+    fn matches(&self, req: &EventRequest) -> bool {
+        commit(req)
+            .map(|c| self.regex.is_match(&c.message))
+            .unwrap_or(false)
+    }
+}
+
+/// Matches commits whose author email matches a regex, ignoring case, e.g.
+/// `\[bot\]@users\.noreply\.github\.com$`. Events which aren't commits never match.
+#[derive(Clone, Debug)]
+pub struct AuthorFilter {
+    regex: Regex,
+}
+
+impl AuthorFilter {
+    pub fn new(regex: &str) -> eyre::Result<Self> {
+        Ok(Self {
+            regex: RegexBuilder::new(regex).case_insensitive(true).build()?,
+        })
+    }
+}
+
+impl EventFilter for AuthorFilter {
+    fn matches(&self, req: &EventRequest) -> bool {
+        commit(req)
+            .map(|c| self.regex.is_match(&c.author.email))
+            .unwrap_or(false)
+    }
+}
+
+/// Matches [Conventional Commits](https://www.conventionalcommits.org), optionally restricted to
+/// some types (`feat`, `fix`) and scopes. Commits which don't follow the convention, and events
+/// which aren't commits, never match.
+#[derive(Clone, Debug, Default)]
+pub struct ConventionalCommitFilter {
+    types: Vec<String>,
+    scopes: Vec<String>,
+    breaking: bool,
+}
+
+impl ConventionalCommitFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn types<I, S>(mut self, types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.types.extend(types.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scopes.extend(scopes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only match breaking changes, marked with `!` or a `BREAKING CHANGE` footer.
+    pub fn breaking(mut self) -> Self {
+        self.breaking = true;
+        self
+    }
+}
+
+impl EventFilter for ConventionalCommitFilter {
+    fn matches(&self, req: &EventRequest) -> bool {
+        let Some(commit) = commit(req) else {
+            return false;
+        };
+        let Some(conventional) = ConventionalCommit::parse(&commit.message) else {
+            return false;
+        };
+
+        (self.types.is_empty()
+            || self
+                .types
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(conventional.kind)))
+            && (self.scopes.is_empty()
+                || conventional
+                    .scope
+                    .map(|scope| self.scopes.iter().any(|s| s == scope))
+                    .unwrap_or(false))
+            && (!self.breaking || conventional.breaking)
+    }
+}
+
+struct ConventionalCommit<'a> {
+    kind: &'a str,
+    scope: Option<&'a str>,
+    breaking: bool,
+}
+
+impl<'a> ConventionalCommit<'a> {
+    fn parse(message: &'a str) -> Option<Self> {
+        let (header, rest) = message.split_once(':')?;
+        let (header, bang) = match header.strip_suffix('!') {
+            Some(header) => (header, true),
+            None => (header, false),
+        };
+        let (kind, scope) = match header.split_once('(') {
+            Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?)),
+            None => (header, None),
+        };
+
+        if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        if !rest.starts_with(' ') {
+            return None;
+        }
+
+        Some(Self {
+            kind,
+            scope,
+            breaking: bang
+                || message.lines().any(|l| {
+                    l.starts_with("BREAKING CHANGE:") || l.starts_with("BREAKING-CHANGE:")
+                }),
+        })
+    }
+}
+
+fn compile<I, S>(patterns: I) -> eyre::Result<Vec<Pattern>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    patterns
+        .into_iter()
+        .map(|p| Pattern::new(p.as_ref()).map_err(eyre::Report::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::events::EventRequest;
+    use crate::git::{ChangeKind, CommitEvent, FileChange, GitEvent};
+
+    use super::{AuthorFilter, ConventionalCommitFilter, EventFilter, MessageFilter, PathFilter};
+
+    fn message(message: &str, email: &str) -> EventRequest {
+        let mut req = commit(&["src/lib.rs"]);
+        if let GitEvent::Commit(commit) = &mut req.git {
+            commit.message = message.into();
+            commit.author.email = email.into();
+        }
+        req
+    }
+
+    fn commit(paths: &[&str]) -> EventRequest {
+        EventRequest {
+            git: GitEvent::Commit(CommitEvent {
+                changes: paths
+                    .iter()
+                    .map(|p| FileChange {
+                        path: PathBuf::from(p),
+                        old_path: None,
+                        kind: ChangeKind::Modified,
+                        insertions: 1,
+                        deletions: 0,
+                    })
+                    .collect(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_includes_directories_and_file_names() {
+        let filter = PathFilter::new(["services/api/**", "*.proto"]).unwrap();
+
+        assert!(filter.matches(&commit(&["services/api/src/main.rs"])));
+        assert!(filter.matches(&commit(&["README.md", "proto/deep/service.proto"])));
+        assert!(!filter.matches(&commit(&["services/web/src/main.rs"])));
+        assert!(!filter.matches(&commit(&[])));
+    }
+
+    #[test]
+    fn test_excludes_take_precedence() {
+        let filter = PathFilter::new(["services/**"])
+            .unwrap()
+            .exclude(["*.md"])
+            .unwrap();
+
+        assert!(filter.matches(&commit(&["services/api/lib.rs"])));
+        assert!(!filter.matches(&commit(&["services/api/README.md"])));
+        assert!(filter.matches(&commit(&["services/api/README.md", "services/api/lib.rs"])));
+    }
+
+    #[test]
+    fn test_message_and_author_compose() {
+        let filter = MessageFilter::new(r"\[skip ci\]")
+            .unwrap()
+            .or(AuthorFilter::new(r"\[bot\]@users\.noreply\.github\.com$").unwrap())
+            .not();
+
+        assert!(filter.matches(&message("fix: a bug", "dev@example.com")));
+        assert!(!filter.matches(&message("docs: typo [skip ci]", "dev@example.com")));
+        assert!(!filter.matches(&message(
+            "chore(deps): bump",
+            "renovate[bot]@users.noreply.github.com"
+        )));
+    }
+
+    #[test]
+    fn test_conventional_commit_types_and_scopes() {
+        let feat_or_fix = ConventionalCommitFilter::new().types(["feat", "fix"]);
+        assert!(feat_or_fix.matches(&message("feat: add x", "")));
+        assert!(feat_or_fix.matches(&message("fix(api)!: remove y", "")));
+        assert!(!feat_or_fix.matches(&message("chore: bump", "")));
+        assert!(!feat_or_fix.matches(&message("Merge branch 'feat'", "")));
+
+        let api = ConventionalCommitFilter::new().scopes(["api"]);
+        assert!(api.matches(&message("fix(api): y", "")));
+        assert!(!api.matches(&message("fix: y", "")));
+
+        let breaking = ConventionalCommitFilter::new().breaking();
+        assert!(breaking.matches(&message("fix(api)!: remove y", "")));
+        assert!(breaking.matches(&message("feat: x\n\nBREAKING CHANGE: gone", "")));
+        assert!(!breaking.matches(&message("feat: x", "")));
+    }
+}
@@ -0,0 +1,292 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use git2::{Cred, CredentialType};
+
+/// How to authenticate against the remote when cloning and fetching.
+#[derive(Clone, Default)]
+pub enum Credentials {
+    /// Use whatever the environment provides: the ssh agent and then `~/.ssh/id_ed25519` and
+    /// `~/.ssh/id_rsa` for ssh remotes, and the configured git credential helpers for https
+    /// remotes.
+    #[default]
+    Default,
+    /// A private key on disk, e.g. a mounted secret.
+    SshKey {
+        /// Defaults to the user in the remote url, or `git`.
+        username: Option<String>,
+        private_key: PathBuf,
+        public_key: Option<PathBuf>,
+        passphrase: Option<String>,
+    },
+    /// A private key held in memory, e.g. injected through an environment variable.
+    SshKeyMemory {
+        username: Option<String>,
+        private_key: String,
+        passphrase: Option<String>,
+    },
+    /// Keys served by a running ssh agent.
+    SshAgent { username: Option<String> },
+    /// Basic auth for https remotes. Most forges accept an access token as the password.
+    UserPassword { username: String, password: String },
+    /// Ask the git credential helpers configured for the remote.
+    CredentialHelper,
+}
+
+const DEFAULT_SSH_USER: &str = "git";
+/// libgit2 keeps asking for credentials while the remote rejects them, give up after this many.
+const MAX_ATTEMPTS: usize = 3;
+
+impl Credentials {
+    /// Reads credentials from the environment, `None` if none of the variables are set.
+    ///
+    /// * `GITEVENTS_GIT_TOKEN`, with `GITEVENTS_GIT_USERNAME` (default `git`): https token
+    /// * `GITEVENTS_SSH_KEY`: the contents of a private key
+    /// * `GITEVENTS_SSH_KEY_PATH`: the path to a private key
+    ///
+    /// `GITEVENTS_SSH_USERNAME` and `GITEVENTS_SSH_KEY_PASSPHRASE` apply to both key variants.
+    pub fn from_env() -> Option<Self> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        if let Some(password) = lookup("GITEVENTS_GIT_TOKEN") {
+            return Some(Self::UserPassword {
+                username: lookup("GITEVENTS_GIT_USERNAME").unwrap_or(DEFAULT_SSH_USER.into()),
+                password,
+            });
+        }
+
+        let username = lookup("GITEVENTS_SSH_USERNAME");
+        let passphrase = lookup("GITEVENTS_SSH_KEY_PASSPHRASE");
+
+        if let Some(private_key) = lookup("GITEVENTS_SSH_KEY") {
+            return Some(Self::SshKeyMemory {
+                username,
+                private_key,
+                passphrase,
+            });
+        }
+
+        lookup("GITEVENTS_SSH_KEY_PATH").map(|path| Self::SshKey {
+            username,
+            private_key: path.into(),
+            public_key: None,
+            passphrase,
+        })
+    }
+
+    /// Builds the libgit2 credentials callback.
+    pub(crate) fn callback(
+        &self,
+    ) -> impl FnMut(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + 'static {
+        let credentials = self.clone();
+        let mut attempts = 0;
+        // The default keys which are left to try, `None` until the agent has been tried
+        let mut default_keys: Option<Vec<PathBuf>> = None;
+
+        move |url, username_from_url, allowed| {
+            let ssh_user = |username: &Option<String>| {
+                username
+                    .clone()
+                    .or(username_from_url.map(String::from))
+                    .unwrap_or(DEFAULT_SSH_USER.into())
+            };
+
+            // libgit2 first asks which user to connect as when the url doesn't contain one
+            if allowed.contains(CredentialType::USERNAME) {
+                let username = match &credentials {
+                    Self::SshKey { username, .. }
+                    | Self::SshKeyMemory { username, .. }
+                    | Self::SshAgent { username } => ssh_user(username),
+                    Self::UserPassword { username, .. } => username.clone(),
+                    Self::Default | Self::CredentialHelper => ssh_user(&None),
+                };
+                return Cred::username(&username);
+            }
+
+            attempts += 1;
+            if attempts > MAX_ATTEMPTS {
+                return Err(auth_failed(url));
+            }
+
+            match &credentials {
+                Self::SshKey {
+                    username,
+                    private_key,
+                    public_key,
+                    passphrase,
+                } => Cred::ssh_key(
+                    &ssh_user(username),
+                    public_key.as_deref(),
+                    private_key,
+                    passphrase.as_deref(),
+                ),
+                Self::SshKeyMemory {
+                    username,
+                    private_key,
+                    passphrase,
+                } => Cred::ssh_key_from_memory(
+                    &ssh_user(username),
+                    None,
+                    private_key,
+                    passphrase.as_deref(),
+                ),
+                Self::SshAgent { username } => Cred::ssh_key_from_agent(&ssh_user(username)),
+                Self::UserPassword { username, password } => {
+                    Cred::userpass_plaintext(username, password)
+                }
+                Self::CredentialHelper => {
+                    Cred::credential_helper(&git2::Config::open_default()?, url, username_from_url)
+                }
+                Self::Default => {
+                    if allowed.contains(CredentialType::SSH_KEY) {
+                        match &mut default_keys {
+                            None => {
+                                default_keys = Some(
+                                    std::env::var_os("HOME")
+                                        .map(|home| default_ssh_keys(Path::new(&home)))
+                                        .unwrap_or_default(),
+                                );
+                                Cred::ssh_key_from_agent(&ssh_user(&None))
+                            }
+                            Some(keys) => match keys.pop() {
+                                Some(key) => Cred::ssh_key(&ssh_user(&None), None, &key, None),
+                                None => Err(auth_failed(url)),
+                            },
+                        }
+                    } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                        Cred::credential_helper(
+                            &git2::Config::open_default()?,
+                            url,
+                            username_from_url,
+                        )
+                    } else {
+                        Cred::default()
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn auth_failed(url: &str) -> git2::Error {
+    git2::Error::new(
+        git2::ErrorCode::Auth,
+        git2::ErrorClass::Callback,
+        format!("authentication failed for {}", url),
+    )
+}
+
+/// The keys in `home` ssh tries when none are configured, in reverse order of preference.
+fn default_ssh_keys(home: &Path) -> Vec<PathBuf> {
+    ["id_rsa", "id_ed25519"]
+        .into_iter()
+        .map(|name| home.join(".ssh").join(name))
+        .filter(|key| key.is_file())
+        .collect()
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const REDACTED: &str = "<redacted>";
+
+        match self {
+            Self::Default => write!(f, "Default"),
+            Self::SshKey {
+                username,
+                private_key,
+                public_key,
+                passphrase,
+            } => f
+                .debug_struct("SshKey")
+                .field("username", username)
+                .field("private_key", private_key)
+                .field("public_key", public_key)
+                .field("passphrase", &passphrase.as_ref().map(|_| REDACTED))
+                .finish(),
+            Self::SshKeyMemory {
+                username,
+                passphrase,
+                ..
+            } => f
+                .debug_struct("SshKeyMemory")
+                .field("username", username)
+                .field("private_key", &REDACTED)
+                .field("passphrase", &passphrase.as_ref().map(|_| REDACTED))
+                .finish(),
+            Self::SshAgent { username } => f
+                .debug_struct("SshAgent")
+                .field("username", username)
+                .finish(),
+            Self::UserPassword { username, .. } => f
+                .debug_struct("UserPassword")
+                .field("username", username)
+                .field("password", &REDACTED)
+                .finish(),
+            Self::CredentialHelper => write!(f, "CredentialHelper"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env::temp_dir;
+
+    use super::{default_ssh_keys, Credentials};
+
+    #[test]
+    fn test_token_from_env() {
+        let env = HashMap::from([("GITEVENTS_GIT_TOKEN", "secret-token")]);
+        let credentials =
+            Credentials::from_lookup(|key| env.get(key).map(|v| v.to_string())).unwrap();
+
+        assert!(matches!(
+            &credentials,
+            Credentials::UserPassword { username, password }
+                if username == "git" && password == "secret-token"
+        ));
+        assert!(!format!("{:?}", credentials).contains("secret-token"));
+    }
+
+    #[test]
+    fn test_ssh_key_from_env() {
+        let env = HashMap::from([
+            ("GITEVENTS_SSH_KEY_PATH", "/run/secrets/id_ed25519"),
+            ("GITEVENTS_SSH_KEY_PASSPHRASE", "hunter2"),
+        ]);
+        let credentials =
+            Credentials::from_lookup(|key| env.get(key).map(|v| v.to_string())).unwrap();
+
+        assert!(matches!(
+            &credentials,
+            Credentials::SshKey { private_key, passphrase: Some(_), .. }
+                if private_key.to_str() == Some("/run/secrets/id_ed25519")
+        ));
+        assert!(!format!("{:?}", credentials).contains("hunter2"));
+    }
+
+    #[test]
+    fn test_nothing_from_env() {
+        assert!(Credentials::from_lookup(|_| None).is_none());
+    }
+
+    #[test]
+    fn test_default_ssh_keys() {
+        let mut home = temp_dir();
+        home.push(uuid::Uuid::new_v4().to_string());
+        let ssh = home.join(".ssh");
+        std::fs::create_dir_all(&ssh).unwrap();
+        assert!(default_ssh_keys(&home).is_empty());
+
+        std::fs::write(ssh.join("id_rsa"), "rsa").unwrap();
+        std::fs::write(ssh.join("id_ed25519"), "ed25519").unwrap();
+        let mut keys = default_ssh_keys(&home);
+        assert_eq!(keys.pop(), Some(ssh.join("id_ed25519")));
+        assert_eq!(keys.pop(), Some(ssh.join("id_rsa")));
+        assert_eq!(keys.pop(), None);
+
+        std::fs::remove_dir_all(home).unwrap();
+    }
+}
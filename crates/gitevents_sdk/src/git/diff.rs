@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use git2::{Commit, Delta, DiffFindOptions, Patch, Repository};
+
+use super::{ChangeKind, FileChange};
+
+/// The files changed by `commit` compared to its first parent.
+///
+/// Root commits are compared to an empty tree. Commits on a shallow boundary have no parent to
+/// compare to, for them nothing is reported.
+pub fn changes(repo: &Repository, commit: &Commit) -> eyre::Result<Vec<FileChange>> {
+    let parent_tree = match commit.parent_count() {
+        0 => None,
+        _ => match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => return Ok(Vec::new()),
+        },
+    };
+
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+    let mut changes = Vec::with_capacity(diff.deltas().len());
+    for (idx, delta) in diff.deltas().enumerate() {
+        let kind = match delta.status() {
+            Delta::Added | Delta::Copied => ChangeKind::Added,
+            Delta::Deleted => ChangeKind::Deleted,
+            Delta::Renamed => ChangeKind::Renamed,
+            Delta::Modified | Delta::Typechange => ChangeKind::Modified,
+            _ => continue,
+        };
+
+        let new_path = delta.new_file().path().map(PathBuf::from);
+        let old_path = delta.old_file().path().map(PathBuf::from);
+        let (path, old_path) = match kind {
+            ChangeKind::Deleted => (old_path, None),
+            ChangeKind::Renamed => (new_path, old_path),
+            _ => (new_path, None),
+        };
+        let Some(path) = path else {
+            continue;
+        };
+
+        let (insertions, deletions) = match Patch::from_diff(&diff, idx)? {
+            Some(patch) => {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                (insertions, deletions)
+            }
+            // Binary files have no lines
+            None => (0, 0),
+        };
+
+        changes.push(FileChange {
+            path,
+            old_path,
+            kind,
+            insertions,
+            deletions,
+        });
+    }
+
+    Ok(changes)
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+
+/// A failed clone or fetch of a remote repository.
+///
+/// Returned (wrapped in `eyre::Report`) from `GitProvider::listen`, so a caller can
+/// `downcast_ref::<GitError>()` to decide whether to retry or alert.
+#[derive(Debug, Clone)]
+pub struct GitError {
+    /// The equivalent git command, e.g. `git fetch origin`.
+    pub command: String,
+    /// The libgit2 error code, the native counterpart of an exit status.
+    pub code: git2::ErrorCode,
+    /// The libgit2 error class, i.e. which subsystem failed (network, ssh, reference, ...).
+    pub class: git2::ErrorClass,
+    /// The libgit2 error message.
+    pub message: String,
+    /// Lines the remote sent on its progress/error channel before failing.
+    pub stderr: Vec<String>,
+}
+
+impl GitError {
+    pub fn new(command: impl Into<String>, err: git2::Error, stderr: Vec<String>) -> Self {
+        Self {
+            command: command.into(),
+            code: err.code(),
+            class: err.class(),
+            message: err.message().to_string(),
+            stderr,
+        }
+    }
+
+    /// Whether the remote rejected the provided credentials.
+    pub fn is_auth(&self) -> bool {
+        self.code == git2::ErrorCode::Auth
+            || matches!(self.class, git2::ErrorClass::Ssh | git2::ErrorClass::Http)
+                && self.message.to_lowercase().contains("auth")
+    }
+
+    /// Whether the failure happened while talking to the remote, and is likely transient.
+    pub fn is_network(&self) -> bool {
+        matches!(
+            self.class,
+            git2::ErrorClass::Net | git2::ErrorClass::Http | git2::ErrorClass::Ssl
+        ) && !self.is_auth()
+    }
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` failed ({:?}/{:?}): {}",
+            self.command, self.class, self.code, self.message
+        )?;
+
+        for line in &self.stderr {
+            write!(f, "\n  remote: {}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for GitError {}
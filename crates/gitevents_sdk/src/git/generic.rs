@@ -1,20 +1,69 @@
-use std::process::Stdio;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use eyre::WrapErr;
 use git2::Repository;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use glob::{MatchOptions, Pattern};
 use tokio::sync::Mutex;
 
+use crate::progress::memory::MemoryProgressStore;
+use crate::progress::{DynProgressStore, Progress};
 use crate::storage::volatile::VolatileStorage;
 use crate::storage::DynStorage;
 
-use super::{GitEvent, GitProvider};
+use super::{
+    diff, native, BranchEvent, CommitEvent, Credentials, GitEvent, GitProvider, RefRewrittenEvent,
+    RepositoryChange, RepositoryEvent, TagChange, TagEvent,
+};
+
+const REMOTE_BRANCH_PREFIX: &str = "refs/remotes/origin/";
+const MIRROR_BRANCH_PREFIX: &str = "refs/heads/";
+
+#[derive(Clone, Debug, Default)]
+pub struct GitGenericOpts {
+    /// Branch names or glob patterns (`main`, `release/*`) to watch. A `*` stays within a
+    /// single path segment, `**` crosses them. When empty, only the default branch is watched.
+    pub branches: Vec<String>,
+    /// Keep a bare mirror instead of a checkout. Events are computed from refs and objects
+    /// alone, and their `path` points at the bare repository. Use `CommitEvent::materialize` to
+    /// get the files of a commit.
+    pub mirror: bool,
+    /// Only fetch the last `depth` commits of history when cloning, like `git clone --depth`.
+    /// Later fetches get every new commit, and a new clone is deepened until it reaches the stored
+    /// progress, so no events are skipped. Partial clones (`--filter=blob:none`) aren't supported
+    /// by libgit2, use `mirror` to skip the checkout.
+    pub depth: Option<u32>,
+    /// How to authenticate against the remote.
+    pub credentials: Credentials,
+}
 
 pub struct GitGeneric {
     url: String,
     storage: DynStorage,
-    progress: Mutex<Option<String>>,
+    opts: GitGenericOpts,
+    branches: Vec<Pattern>,
+    /// Last polled commit per watched ref, keyed by the ref name on the remote, the next poll
+    /// continues from there. `None` until it's been loaded from the progress store on the first
+    /// poll.
+    progress: Mutex<Option<Progress>>,
+    /// The progress as last loaded from or written to the progress store, advanced by the
+    /// events passed to [`GitProvider::processed`].
+    stored_progress: Option<Progress>,
+    progress_store: DynProgressStore,
+    /// Tags seen on the previous poll, `None` until the first poll has taken a baseline.
+    tags: Mutex<Option<HashMap<String, TagSnapshot>>>,
+    /// Branches on the remote and their tips as of the previous poll, `None` until the first
+    /// poll has taken a baseline.
+    remote_branches: Mutex<Option<BTreeMap<String, git2::Oid>>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TagSnapshot {
+    /// The object the tag ref points at, the tag object itself for annotated tags.
+    target: git2::Oid,
+    commit: git2::Oid,
+    message: Option<String>,
 }
 
 impl GitGeneric {
@@ -22,140 +71,416 @@ impl GitGeneric {
         Self {
             url: url.into(),
             storage: Arc::new(VolatileStorage::new()),
+            opts: GitGenericOpts::default(),
+            branches: Vec::new(),
             progress: Mutex::new(None),
+            stored_progress: None,
+            progress_store: Arc::new(MemoryProgressStore::new()),
+            tags: Mutex::new(None),
+            remote_branches: Mutex::new(None),
+        }
+    }
+
+    pub fn with_opts(url: impl Into<String>, opts: &GitGenericOpts) -> eyre::Result<Self> {
+        let mut git = Self::new(url);
+        git.branches = opts
+            .branches
+            .iter()
+            .map(|b| Pattern::new(b))
+            .collect::<Result<_, _>>()?;
+        git.opts = opts.clone();
+
+        Ok(git)
+    }
+
+    /// Where the repository is cloned to, a temporary directory by default. The storage can be
+    /// shared with other repositories.
+    pub fn set_storage(mut self, storage: DynStorage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Persists how far each watched branch has been processed, which is once every handler is
+    /// done with the events of a poll, see [`GitProvider::processed`]. With a durable store, a
+    /// restarted listener emits every commit pushed since the previous run, or which was still
+    /// being handled, instead of taking the current tips as a new baseline. Tag and branch events
+    /// still start from a new baseline.
+    pub fn set_progress_store(mut self, store: DynProgressStore) -> Self {
+        self.progress_store = store;
+        self
+    }
+
+    /// Where the remote's branches are stored locally.
+    fn branch_prefix(&self) -> &'static str {
+        match self.opts.mirror {
+            true => MIRROR_BRANCH_PREFIX,
+            false => REMOTE_BRANCH_PREFIX,
+        }
+    }
+
+    /// Resolves the watched branches to their current tips, keyed by `refs/heads/<branch>`.
+    fn watched_refs(&self, repo: &Repository) -> eyre::Result<BTreeMap<String, git2::Oid>> {
+        let mut refs = BTreeMap::new();
+
+        if self.branches.is_empty() {
+            let head = repo.head()?;
+            let branch = head
+                .shorthand()
+                .ok_or(eyre::anyhow!("HEAD is not pointing at a valid branch name"))?;
+            let tip = head
+                .target()
+                .ok_or(eyre::anyhow!("HEAD is not pointing at a commit"))?;
+            refs.insert(format!("refs/heads/{}", branch), tip);
+
+            return Ok(refs);
+        }
+
+        let match_opts = MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+
+        let prefix = self.branch_prefix();
+        for reference in repo.references_glob(&format!("{}*", prefix))? {
+            let reference = reference?;
+            let (Some(name), Some(tip)) = (reference.name(), reference.target()) else {
+                // Symbolic refs such as origin/HEAD only alias another branch
+                continue;
+            };
+            let branch = &name[prefix.len()..];
+
+            if self
+                .branches
+                .iter()
+                .any(|p| p.matches_with(branch, match_opts))
+            {
+                refs.insert(format!("refs/heads/{}", branch), tip);
+            }
+        }
+
+        Ok(refs)
+    }
+
+    fn commit_event(
+        &self,
+        repo: &Repository,
+        oid: git2::Oid,
+        reference: &str,
+        path: &std::path::Path,
+    ) -> eyre::Result<CommitEvent> {
+        let commit = repo.find_commit(oid)?;
+        let message = String::from_utf8_lossy(commit.message_bytes()).into_owned();
+
+        Ok(CommitEvent {
+            commit: oid.to_string(),
+            reference: reference.to_string(),
+            url: self.url.clone(),
+            author: commit.author().into(),
+            committer: commit.committer().into(),
+            summary: message.lines().next().unwrap_or_default().to_string(),
+            message,
+            parents: commit.parent_ids().map(|p| p.to_string()).collect(),
+            changes: diff::changes(repo, &commit)?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Every branch on the remote and its tip, keyed by `refs/heads/<branch>`.
+    fn remote_branch_snapshot(
+        &self,
+        repo: &Repository,
+    ) -> eyre::Result<BTreeMap<String, git2::Oid>> {
+        let mut branches = BTreeMap::new();
+
+        let prefix = self.branch_prefix();
+        for reference in repo.references_glob(&format!("{}*", prefix))? {
+            let reference = reference?;
+            if let (Some(name), Some(tip)) = (reference.name(), reference.target()) {
+                let branch = &name[prefix.len()..];
+                branches.insert(format!("refs/heads/{}", branch), tip);
+            }
+        }
+
+        Ok(branches)
+    }
+
+    /// The branch events since the `previous` snapshot, and the snapshot to compare the next poll
+    /// to.
+    fn branch_events(
+        &self,
+        repo: &Repository,
+        previous: Option<&BTreeMap<String, git2::Oid>>,
+        path: &std::path::Path,
+    ) -> eyre::Result<(Vec<GitEvent>, BTreeMap<String, git2::Oid>)> {
+        let current = self.remote_branch_snapshot(repo)?;
+        let Some(previous) = previous else {
+            return Ok((Vec::new(), current));
+        };
+
+        let event = |reference: &str, tip: &git2::Oid| BranchEvent {
+            reference: reference.to_string(),
+            url: self.url.clone(),
+            commit: tip.to_string(),
+            path: path.to_path_buf(),
+        };
+
+        let mut events = Vec::new();
+        for (reference, tip) in &current {
+            if !previous.contains_key(reference) {
+                events.push(GitEvent::BranchCreated(event(reference, tip)));
+            }
+        }
+        for (reference, tip) in previous {
+            if !current.contains_key(reference) {
+                events.push(GitEvent::BranchDeleted(event(reference, tip)));
+            }
+        }
+
+        Ok((events, current))
+    }
+
+    fn tag_snapshot(repo: &Repository) -> eyre::Result<HashMap<String, TagSnapshot>> {
+        let mut tags = HashMap::new();
+
+        for reference in repo.references_glob("refs/tags/*")? {
+            let reference = reference?;
+            let (Some(name), Some(target)) = (reference.shorthand(), reference.target()) else {
+                continue;
+            };
+            let Ok(commit) = reference.peel_to_commit() else {
+                // Tags on trees or blobs aren't releases, ignore them
+                continue;
+            };
+            let message = match reference.peel_to_tag() {
+                Ok(tag) => tag.message().map(String::from),
+                Err(_) => None,
+            };
+
+            tags.insert(
+                name.to_string(),
+                TagSnapshot {
+                    target,
+                    commit: commit.id(),
+                    message,
+                },
+            );
         }
+
+        Ok(tags)
+    }
+
+    /// The tag events since the `previous` snapshot, and the snapshot to compare the next poll to.
+    fn tag_events(
+        &self,
+        repo: &Repository,
+        previous: Option<&HashMap<String, TagSnapshot>>,
+        path: &std::path::Path,
+    ) -> eyre::Result<(Vec<GitEvent>, HashMap<String, TagSnapshot>)> {
+        let current = Self::tag_snapshot(repo)?;
+        let Some(previous) = previous else {
+            // The tags present when first cloned are the baseline, not new releases
+            return Ok((Vec::new(), current));
+        };
+
+        let event = |name: &str, change, tag: &TagSnapshot| {
+            GitEvent::Tag(TagEvent {
+                name: name.to_string(),
+                url: self.url.clone(),
+                change,
+                commit: tag.commit.to_string(),
+                message: tag.message.clone(),
+                path: path.to_path_buf(),
+            })
+        };
+
+        let mut events = Vec::new();
+        for (name, tag) in current.iter().collect::<BTreeMap<_, _>>() {
+            match previous.get(name) {
+                None => events.push(event(name, TagChange::Created, tag)),
+                Some(prev) if prev.target != tag.target => events.push(event(
+                    name,
+                    TagChange::Moved {
+                        previous: prev.commit.to_string(),
+                    },
+                    tag,
+                )),
+                Some(_) => {}
+            }
+        }
+        for (name, tag) in previous.iter().collect::<BTreeMap<_, _>>() {
+            if !current.contains_key(name) {
+                events.push(event(name, TagChange::Deleted, tag));
+            }
+        }
+
+        Ok((events, current))
     }
 }
 
-#[async_trait]
-impl GitProvider for GitGeneric {
-    async fn listen(&mut self) -> eyre::Result<Option<GitEvent>> {
-        match self.storage.exists().await? {
-            Some(path) => {
-                let mut cmd = tokio::process::Command::new("git")
-                    .args(&["pull"])
-                    .current_dir(&path)
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()?;
-
-                let stdout = cmd
-                    .stdout
-                    .take()
-                    .ok_or(eyre::anyhow!("failed to capture stdout of cmd"))?;
-                let stderr = cmd
-                    .stderr
-                    .take()
-                    .ok_or(eyre::anyhow!("failed to capture stdout of cmd"))?;
-                let mut reader = BufReader::new(stdout).lines();
-                let mut errreader = BufReader::new(stderr).lines();
-
-                tokio::spawn(async move {
-                    while let Ok(Some(line)) = reader.next_line().await {
-                        tracing::debug!(line = line, "out: git pull");
-                    }
-                });
+impl GitGeneric {
+    async fn poll(&mut self) -> eyre::Result<Vec<GitEvent>> {
+        let mut events = Vec::new();
 
-                tokio::spawn(async move {
-                    while let Ok(Some(line)) = errreader.next_line().await {
-                        tracing::debug!(line = line, "err: git pull");
-                    }
-                });
-
-                let status = cmd.wait().await?.to_string();
-                tracing::debug!(status = status, "git pull finished");
-
-                let mut p = self.progress.lock().await;
-                match p.as_mut() {
-                    Some(p) => {
-                        let repo = Repository::open(path.clone())?;
-                        let head = repo.head()?.target().unwrap();
-                        let mut revwalk = repo.revwalk()?;
-                        revwalk.set_sorting(git2::Sort::NONE)?; //| git2::Sort::REVERSE)?;
-                        let start = git2::Oid::from_str(p)?;
-                        revwalk.hide(start)?;
-                        revwalk.push(head)?;
-
-                        if let Some(rev) = revwalk.next() {
-                            let revstr = rev?.to_string();
-                            tracing::trace!(progress = &revstr, "storing progress");
-                            *p = revstr.clone();
-
-                            return Ok(Some(GitEvent {
-                                commit: revstr,
-                                path: path.clone(),
-                            }));
-                        }
-                    }
-                    None => {
-                        eyre::bail!(
-                            "inconsistency found, object should not already have progress stored"
-                        );
-                    }
+        let path = match self.storage.exists(&self.url).await? {
+            Some(path) => {
+                native::pull(&path, &self.opts).await?;
+                path
+            }
+            None => {
+                let path = self.storage.allocate(&self.url).await?;
+                if let Err(e) = native::clone(&self.url, &path, &self.opts).await {
+                    // Start from an empty directory on the next attempt
+                    self.storage.release(&self.url).await?;
+                    return Err(e);
                 }
-
-                Ok(None)
+                events.push(GitEvent::Repository(RepositoryEvent {
+                    url: self.url.clone(),
+                    change: RepositoryChange::Cloned,
+                    path: path.clone(),
+                }));
+                path
             }
+        };
+
+        let mut repo = Repository::open(&path)?;
+        let mut polled = self.progress.lock().await;
+        let mut progress = match &*polled {
+            Some(progress) => progress.clone(),
             None => {
-                let path = self.storage.allocate().await?;
-
-                let mut cmd = tokio::process::Command::new("git")
-                    .args(&[
-                        "clone",
-                        self.url.as_str(),
-                        path.to_str()
-                            .ok_or(eyre::anyhow!("could not transform path into str"))?,
-                    ])
-                    .stdout(Stdio::piped())
-                    .stderr(Stdio::piped())
-                    .spawn()?;
-
-                let stdout = cmd
-                    .stdout
-                    .take()
-                    .ok_or(eyre::anyhow!("failed to capture stdout of cmd"))?;
-                let stderr = cmd
-                    .stderr
-                    .take()
-                    .ok_or(eyre::anyhow!("failed to capture stdout of cmd"))?;
-                let mut reader = BufReader::new(stdout).lines();
-                let mut errreader = BufReader::new(stderr).lines();
-
-                tokio::spawn(async move {
-                    while let Ok(Some(line)) = reader.next_line().await {
-                        tracing::debug!(line = line, "out: git clone");
+                let progress = self.progress_store.load(&self.url).await?;
+                self.stored_progress = Some(progress.clone());
+                progress
+            }
+        };
+        let mut remote_branches = self.remote_branches.lock().await;
+        let (branch_events, current_branches) =
+            self.branch_events(&repo, remote_branches.as_ref(), &path)?;
+        events.extend(branch_events);
+
+        let watched = self.watched_refs(&repo)?;
+        // A watched branch which is deleted and later recreated starts from a new baseline
+        progress.retain(|reference, _| watched.contains_key(reference));
+
+        for (reference, tip) in watched {
+            match progress.get(&reference) {
+                Some(start) => {
+                    let start = git2::Oid::from_str(start)?;
+                    if start == tip {
+                        continue;
                     }
-                });
 
-                tokio::spawn(async move {
-                    while let Ok(Some(line)) = errreader.next_line().await {
-                        tracing::debug!(line = line, "err: git clone");
+                    if repo.is_shallow() && repo.find_commit(start).is_err() {
+                        // A fresh shallow clone, e.g. after a restart, may not reach back to the
+                        // stored progress yet
+                        native::deepen(&path, &self.opts, start).await?;
+                        repo = Repository::open(&path)?;
                     }
-                });
-
-                let status = cmd.wait().await?.to_string();
-                tracing::debug!(status = status, "git clone finished");
-
-                let mut p = self.progress.lock().await;
-                match p.as_mut() {
-                    Some(_) => eyre::bail!(
-                        "inconsistency found, object should not already have progress stored"
-                    ),
-                    None => {
-                        let repo = Repository::open(path.clone())?;
-                        let head = repo.head()?;
-                        let revstr = head.target().unwrap().to_string();
-                        tracing::trace!(progress = &revstr, "storing progress");
-                        *p = Some(revstr.clone());
-
-                        return Ok(Some(GitEvent {
-                            commit: revstr,
+
+                    let is_ancestor =
+                        repo.find_commit(start).is_ok() && repo.graph_descendant_of(tip, start)?;
+                    if !is_ancestor {
+                        // The branch was force-pushed, the commits since `start` can't be walked
+                        let merge_base = repo.merge_base(start, tip).ok();
+                        tracing::debug!(
+                            reference = &reference,
+                            old = start.to_string(),
+                            new = tip.to_string(),
+                            "ref rewritten"
+                        );
+                        events.push(GitEvent::RefRewritten(RefRewrittenEvent {
+                            reference: reference.clone(),
+                            url: self.url.clone(),
+                            old: start.to_string(),
+                            new: tip.to_string(),
+                            merge_base: merge_base.map(|oid| oid.to_string()),
                             path: path.clone(),
                         }));
+                        progress.insert(reference, tip.to_string());
+                        continue;
+                    }
+
+                    let mut revwalk = repo.revwalk()?;
+                    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+                    revwalk.hide(start)?;
+                    revwalk.push(tip)?;
+
+                    for rev in revwalk {
+                        events.push(GitEvent::Commit(
+                            self.commit_event(&repo, rev?, &reference, &path)?,
+                        ));
                     }
                 }
+                None => {
+                    // First time this ref is seen, its tip becomes the baseline
+                    events.push(GitEvent::Commit(
+                        self.commit_event(&repo, tip, &reference, &path)?,
+                    ));
+                }
+            }
+
+            let revstr = tip.to_string();
+            tracing::trace!(
+                reference = &reference,
+                progress = &revstr,
+                "storing progress"
+            );
+            progress.insert(reference, revstr);
+        }
+
+        let mut tags = self.tags.lock().await;
+        let (tag_events, current_tags) = self.tag_events(&repo, tags.as_ref(), &path)?;
+        events.extend(tag_events);
+
+        // Only taken once nothing can fail anymore, a failed poll is retried from the previous
+        // snapshots instead of dropping its events.
+        *polled = Some(progress);
+        *remote_branches = Some(current_branches);
+        *tags = Some(current_tags);
+
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl GitProvider for GitGeneric {
+    async fn listen(&mut self) -> eyre::Result<Vec<GitEvent>> {
+        let url = self.url.clone();
+        self.poll()
+            .await
+            .wrap_err_with(|| format!("failed to listen to {}", url))
+    }
+
+    async fn processed(&mut self, events: &[GitEvent]) -> eyre::Result<()> {
+        let mut progress = match self.stored_progress.clone() {
+            Some(progress) => progress,
+            // Nothing was listened to yet
+            None => return Ok(()),
+        };
+        for event in events {
+            match event {
+                GitEvent::Commit(e) => {
+                    progress.insert(e.reference.clone(), e.commit.clone());
+                }
+                GitEvent::RefRewritten(e) => {
+                    progress.insert(e.reference.clone(), e.new.clone());
+                }
+                GitEvent::BranchDeleted(e) => {
+                    progress.remove(&e.reference);
+                }
+                _ => {}
             }
         }
+        if self.stored_progress.as_ref() == Some(&progress) {
+            return Ok(());
+        }
+
+        self.progress_store
+            .store(&self.url, &progress)
+            .await
+            .wrap_err_with(|| format!("failed to store the progress of {}", self.url))?;
+        self.stored_progress = Some(progress);
+
+        Ok(())
     }
 }
 
@@ -163,15 +488,23 @@ impl GitProvider for GitGeneric {
 mod tests {
     use std::env::temp_dir;
     use std::fs::write;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
 
     use tokio::fs::{create_dir_all, remove_dir_all};
     use tracing::info;
     use tracing_test::traced_test;
 
-    use crate::git::GitProvider;
+    use crate::git::{
+        ChangeKind, CommitEvent, GitError, GitEvent, GitProvider, RepositoryChange, TagChange,
+        TagEvent,
+    };
+    use crate::progress::file::FileProgressStore;
+    use crate::storage::persistent::PersistentStorage;
+    use crate::storage::volatile::VolatileStorage;
+    use crate::storage::DynStorage;
 
-    use super::GitGeneric;
+    use super::{GitGeneric, GitGenericOpts};
 
     #[tokio::test]
     #[traced_test]
@@ -190,12 +523,21 @@ mod tests {
 
         git_commit_all(&tempdir, "next commit").await.unwrap();
 
-        let mut git = GitGeneric::new(tempdir.to_str().unwrap());
-        let event = git.listen().await.unwrap();
+        let url = tempdir.to_str().unwrap();
+        let mut git = GitGeneric::new(url);
+        let events = git.listen().await.unwrap();
 
-        assert!(event.is_none());
+        assert_eq!(events.len(), 2);
+        let GitEvent::Repository(cloned) = &events[0] else {
+            panic!(
+                "expected the clone to be reported first, got: {:?}",
+                events[0]
+            );
+        };
+        assert_eq!(cloned.change, RepositoryChange::Cloned);
+        assert_eq!(events[1].url(), url);
+        assert!(events[1].reference().unwrap().starts_with("refs/heads/"));
         assert!(logs_contain("git clone finished"));
-        assert!(logs_contain("err: git clone"));
 
         let mut file_path3 = tempdir.clone();
         file_path3.push("readme3.md");
@@ -203,11 +545,10 @@ mod tests {
 
         git_commit_all(&tempdir, "next commit 3").await.unwrap();
 
-        let event = git.listen().await.unwrap();
+        let events = git.listen().await.unwrap();
 
-        assert!(event.is_some());
+        assert_eq!(events.len(), 1);
         assert!(logs_contain("git pull finished"));
-        assert!(logs_contain("err: git pull"));
         assert!(logs_contain("storing progress"));
 
         let mut file_path3 = tempdir.clone();
@@ -216,23 +557,750 @@ mod tests {
 
         git_commit_all(&tempdir, "next commit 4").await.unwrap();
 
-        let event = git.listen().await.unwrap();
+        let events = git.listen().await.unwrap();
 
-        assert!(event.is_some());
+        assert_eq!(events.len(), 1);
         assert!(logs_contain("git pull finished"));
-        assert!(logs_contain("err: git pull"));
         assert!(logs_contain("storing progress"));
 
         remove_dir_all(tempdir).await.unwrap();
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn test_emits_every_commit_between_polls() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+
+        let mut git = GitGeneric::new(tempdir.to_str().unwrap());
+        let events = git.listen().await.unwrap();
+        assert_eq!(commits(&events).len(), 1);
+
+        let mut expected = Vec::new();
+        for i in 0..3 {
+            write(&file_path, format!("Some file {i}")).unwrap();
+            git_commit_all(&tempdir, format!("commit {i}"))
+                .await
+                .unwrap();
+            expected.push(git_rev_parse_head(&tempdir).await.unwrap());
+        }
+
+        let events = git.listen().await.unwrap();
+        let commits = commits(&events)
+            .into_iter()
+            .map(|e| e.commit.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(commits, expected);
+
+        let events = git.listen().await.unwrap();
+        assert!(events.is_empty());
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resumes_from_stored_progress() {
+        let tempdir = git_init().await.unwrap();
+        let store_path = tempdir.with_extension("progress.json");
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+
+        let url = tempdir.to_str().unwrap();
+        let mut git =
+            GitGeneric::new(url).set_progress_store(Arc::new(FileProgressStore::new(&store_path)));
+        let events = git.listen().await.unwrap();
+        assert_eq!(commits(&events).len(), 1);
+        git.processed(&events).await.unwrap();
+        drop(git);
+
+        // Pushed while nothing was listening
+        let mut expected = Vec::new();
+        for i in 0..2 {
+            write(&file_path, format!("Some file {i}")).unwrap();
+            git_commit_all(&tempdir, format!("commit {i}"))
+                .await
+                .unwrap();
+            expected.push(git_rev_parse_head(&tempdir).await.unwrap());
+        }
+
+        let mut git =
+            GitGeneric::new(url).set_progress_store(Arc::new(FileProgressStore::new(&store_path)));
+        let events = git.listen().await.unwrap();
+        assert!(matches!(events[0], GitEvent::Repository(_)));
+        let commits = commits(&events)
+            .into_iter()
+            .map(|e| e.commit.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(commits, expected);
+
+        remove_dir_all(tempdir).await.unwrap();
+        tokio::fs::remove_file(store_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unprocessed_events_are_emitted_again() {
+        let tempdir = git_init().await.unwrap();
+        let store_path = tempdir.with_extension("progress.json");
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+
+        let url = tempdir.to_str().unwrap();
+        let listener = || {
+            GitGeneric::new(url).set_progress_store(Arc::new(FileProgressStore::new(&store_path)))
+        };
+        let mut git = listener();
+        let events = git.listen().await.unwrap();
+        git.processed(&events).await.unwrap();
+
+        write(&file_path, "Some other file").unwrap();
+        git_commit_all(&tempdir, "still handled").await.unwrap();
+        let expected = git_rev_parse_head(&tempdir).await.unwrap();
+
+        // Stopped while the handlers were still busy with the commit, but already done with the
+        // next, empty, poll
+        let events = git.listen().await.unwrap();
+        assert_eq!(commits(&events)[0].commit, expected);
+        let events = git.listen().await.unwrap();
+        assert!(events.is_empty());
+        git.processed(&events).await.unwrap();
+        drop(git);
+
+        let events = listener().listen().await.unwrap();
+        let commits = commits(&events);
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].commit, expected);
+
+        remove_dir_all(tempdir).await.unwrap();
+        tokio::fs::remove_file(store_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reuses_persistent_clone() {
+        let tempdir = git_init().await.unwrap();
+        let root = tempdir.with_extension("storage");
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+
+        let url = tempdir.to_str().unwrap();
+        let listener = || {
+            GitGeneric::new(url)
+                .set_storage(Arc::new(PersistentStorage::new(&root)))
+                .set_progress_store(Arc::new(FileProgressStore::new(root.join("progress.json"))))
+        };
+
+        let mut git = listener();
+        let events = git.listen().await.unwrap();
+        assert!(matches!(events[0], GitEvent::Repository(_)));
+        git.processed(&events).await.unwrap();
+
+        write(&file_path, "Some other file").unwrap();
+        git_commit_all(&tempdir, "while down").await.unwrap();
+
+        let events = listener().listen().await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(commits(&events)[0].summary, "while down");
+
+        remove_dir_all(tempdir).await.unwrap();
+        remove_dir_all(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_repositories_share_storage() {
+        let storage: DynStorage = Arc::new(VolatileStorage::new());
+
+        let mut repos = Vec::new();
+        for name in ["a", "b"] {
+            let tempdir = git_init().await.unwrap();
+            write(tempdir.join("readme.md"), name).unwrap();
+            git_commit_all(&tempdir, format!("commit in {name}"))
+                .await
+                .unwrap();
+            repos.push(tempdir);
+        }
+
+        for tempdir in &repos {
+            let url = tempdir.to_str().unwrap();
+            let mut git = GitGeneric::new(url).set_storage(storage.clone());
+            let events = git.listen().await.unwrap();
+
+            assert!(matches!(events[0], GitEvent::Repository(_)));
+            assert_eq!(events[1].url(), url);
+            assert_eq!(
+                storage.exists(url).await.unwrap().as_deref(),
+                Some(events[1].path())
+            );
+        }
+        assert_ne!(
+            storage.exists(repos[0].to_str().unwrap()).await.unwrap(),
+            storage.exists(repos[1].to_str().unwrap()).await.unwrap()
+        );
+
+        for tempdir in repos {
+            remove_dir_all(tempdir).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commit_event_carries_metadata() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+        let parent = git_rev_parse_head(&tempdir).await.unwrap();
+
+        let url = tempdir.to_str().unwrap();
+        let mut git = GitGeneric::new(url);
+        git.listen().await.unwrap();
+
+        write(&file_path, "Some change").unwrap();
+        git_commit_all(&tempdir, "feat: some change\n\nWith a body")
+            .await
+            .unwrap();
+
+        let events = git.listen().await.unwrap();
+        let event = commits(&events)[0];
+        assert_eq!(event.url, url);
+        assert_eq!(event.summary, "feat: some change");
+        assert_eq!(event.message, "feat: some change\n\nWith a body\n");
+        assert_eq!(event.parents, vec![parent]);
+        assert_eq!(event.author.name, "gitevents");
+        assert_eq!(event.author.email, "gitevents@example.com");
+        assert_eq!(event.committer.email, "gitevents@example.com");
+        assert!(event.author.time > 0);
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_commit_event_lists_changed_files() {
+        let tempdir = git_init().await.unwrap();
+
+        let file = |name: &str| {
+            let mut path = tempdir.clone();
+            path.push(name);
+            path
+        };
+        write(file("modified.md"), "one\ntwo\n").unwrap();
+        write(file("deleted.md"), "gone\n").unwrap();
+        write(file("renamed.md"), "a\nb\nc\nd\ne\n").unwrap();
+        git_commit_all(&tempdir, "initial files").await.unwrap();
+
+        let mut git_generic = GitGeneric::new(tempdir.to_str().unwrap());
+        git_generic.listen().await.unwrap();
+
+        write(file("modified.md"), "one\nthree\nfour\n").unwrap();
+        std::fs::remove_file(file("deleted.md")).unwrap();
+        std::fs::rename(file("renamed.md"), file("moved.md")).unwrap();
+        write(file("added.md"), "new\n").unwrap();
+        git(&tempdir, &["add", "-A"]).await.unwrap();
+        git_commit_all(&tempdir, "change files").await.unwrap();
+
+        let events = git_generic.listen().await.unwrap();
+        let mut changes = commits(&events)[0].changes.clone();
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let summary = changes
+            .iter()
+            .map(|c| {
+                (
+                    c.path.to_str().unwrap(),
+                    c.old_path.as_ref().and_then(|p| p.to_str()),
+                    c.kind,
+                    c.insertions,
+                    c.deletions,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("added.md", None, ChangeKind::Added, 1, 0),
+                ("deleted.md", None, ChangeKind::Deleted, 0, 1),
+                ("modified.md", None, ChangeKind::Modified, 2, 1),
+                ("moved.md", Some("renamed.md"), ChangeKind::Renamed, 0, 0),
+            ]
+        );
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_clone_failure_is_returned() {
+        let mut missing = temp_dir();
+        missing.push(uuid::Uuid::new_v4().to_string());
+
+        let mut git = GitGeneric::new(missing.to_str().unwrap());
+        let err = git.listen().await.unwrap_err();
+        let err = err.downcast_ref::<GitError>().unwrap();
+
+        assert!(err.command.starts_with("git clone "));
+        assert!(!err.message.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tracks_progress_per_branch() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+
+        git(&tempdir, &["branch", "release/1"]).await.unwrap();
+        git(&tempdir, &["branch", "release/2"]).await.unwrap();
+        git(&tempdir, &["branch", "feature/x"]).await.unwrap();
+
+        let mut git_generic = GitGeneric::with_opts(
+            tempdir.to_str().unwrap(),
+            &GitGenericOpts {
+                branches: vec!["release/*".into()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let events = git_generic.listen().await.unwrap();
+        let references = commits(&events)
+            .into_iter()
+            .map(|e| e.reference.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            vec!["refs/heads/release/1", "refs/heads/release/2"]
+        );
+
+        git(&tempdir, &["checkout", "release/1"]).await.unwrap();
+        write(&file_path, "Release fix").unwrap();
+        git_commit_all(&tempdir, "release fix").await.unwrap();
+        let fix = git_rev_parse_head(&tempdir).await.unwrap();
+
+        let events = git_generic.listen().await.unwrap();
+        let events = commits(&events);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].reference, "refs/heads/release/1");
+        assert_eq!(events[0].commit, fix);
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reports_tag_changes() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+        let first = git_rev_parse_head(&tempdir).await.unwrap();
+        git(&tempdir, &["tag", "existing"]).await.unwrap();
+
+        let mut git_generic = GitGeneric::new(tempdir.to_str().unwrap());
+        git_generic.listen().await.unwrap();
+
+        git(&tempdir, &["tag", "-a", "v1.0.0", "-m", "first release"])
+            .await
+            .unwrap();
+        let events = git_generic.listen().await.unwrap();
+        let tags = tag_events(&events);
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "v1.0.0");
+        assert_eq!(tags[0].change, TagChange::Created);
+        assert_eq!(tags[0].commit, first);
+        assert_eq!(tags[0].message.as_deref(), Some("first release\n"));
+
+        write(&file_path, "Next").unwrap();
+        git_commit_all(&tempdir, "next").await.unwrap();
+        let second = git_rev_parse_head(&tempdir).await.unwrap();
+        git(&tempdir, &["tag", "-f", "existing"]).await.unwrap();
+        git(&tempdir, &["tag", "-d", "v1.0.0"]).await.unwrap();
+
+        let events = git_generic.listen().await.unwrap();
+        assert_eq!(commits(&events).len(), 1);
+        let tags = tag_events(&events);
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].name, "existing");
+        assert_eq!(tags[0].change, TagChange::Moved { previous: first });
+        assert_eq!(tags[0].commit, second);
+        assert_eq!(tags[0].message, None);
+        assert_eq!(tags[1].name, "v1.0.0");
+        assert_eq!(tags[1].change, TagChange::Deleted);
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_failed_poll_keeps_branch_and_tag_events() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+
+        let mut git_generic = GitGeneric::new(tempdir.to_str().unwrap());
+        git_generic.listen().await.unwrap();
+
+        write(&file_path, "Next").unwrap();
+        git_commit_all(&tempdir, "next").await.unwrap();
+        git(&tempdir, &["tag", "v1.0.0"]).await.unwrap();
+        git(&tempdir, &["branch", "feature/x"]).await.unwrap();
+
+        // Fails the poll once the branches have been looked at
+        let progress = git_generic.progress.lock().await.clone().unwrap();
+        let corrupted = progress
+            .keys()
+            .map(|reference| (reference.clone(), "not a commit".to_string()))
+            .collect();
+        *git_generic.progress.lock().await = Some(corrupted);
+        assert!(git_generic.listen().await.is_err());
+        *git_generic.progress.lock().await = Some(progress);
+
+        let events = git_generic.listen().await.unwrap();
+        assert_eq!(commits(&events).len(), 1);
+        let tags = tag_events(&events);
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "v1.0.0");
+        let created = events
+            .iter()
+            .filter(|e| matches!(e, GitEvent::BranchCreated(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].reference().unwrap(), "refs/heads/feature/x");
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reports_force_push_as_rewrite() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+        let base = git_rev_parse_head(&tempdir).await.unwrap();
+
+        write(&file_path, "To be rewritten").unwrap();
+        git_commit_all(&tempdir, "to be rewritten").await.unwrap();
+        let old = git_rev_parse_head(&tempdir).await.unwrap();
+
+        let mut git_generic = GitGeneric::new(tempdir.to_str().unwrap());
+        git_generic.listen().await.unwrap();
+
+        git(&tempdir, &["reset", "--hard", &base]).await.unwrap();
+        write(&file_path, "Rewritten").unwrap();
+        git_commit_all(&tempdir, "rewritten").await.unwrap();
+        let new = git_rev_parse_head(&tempdir).await.unwrap();
+
+        let events = git_generic.listen().await.unwrap();
+        assert_eq!(events.len(), 1);
+        let GitEvent::RefRewritten(rewrite) = &events[0] else {
+            panic!("expected a rewrite, got: {:?}", events[0]);
+        };
+        assert_eq!(rewrite.old, old);
+        assert_eq!(rewrite.new, new);
+        assert_eq!(rewrite.merge_base.as_deref(), Some(base.as_str()));
+
+        write(&file_path, "After rewrite").unwrap();
+        git_commit_all(&tempdir, "after rewrite").await.unwrap();
+        let after = git_rev_parse_head(&tempdir).await.unwrap();
+
+        let events = git_generic.listen().await.unwrap();
+        let commits = commits(&events);
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].commit, after);
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reports_branch_creation_and_deletion() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+        let head = git_rev_parse_head(&tempdir).await.unwrap();
+
+        let mut git_generic = GitGeneric::new(tempdir.to_str().unwrap());
+        git_generic.listen().await.unwrap();
+
+        git(&tempdir, &["branch", "feature/x"]).await.unwrap();
+        let events = git_generic.listen().await.unwrap();
+        assert_eq!(events.len(), 1);
+        let GitEvent::BranchCreated(created) = &events[0] else {
+            panic!("expected a created branch, got: {:?}", events[0]);
+        };
+        assert_eq!(created.reference, "refs/heads/feature/x");
+        assert_eq!(created.commit, head);
+
+        git(&tempdir, &["branch", "-D", "feature/x"]).await.unwrap();
+        let events = git_generic.listen().await.unwrap();
+        assert_eq!(events.len(), 1);
+        let GitEvent::BranchDeleted(deleted) = &events[0] else {
+            panic!("expected a deleted branch, got: {:?}", events[0]);
+        };
+        assert_eq!(deleted.reference, "refs/heads/feature/x");
+        assert_eq!(deleted.commit, head);
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_listens_through_bare_mirror() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        write(&file_path, "Some file").unwrap();
+        git_commit_all(&tempdir, "initial file").await.unwrap();
+
+        let mut git_generic = GitGeneric::with_opts(
+            tempdir.to_str().unwrap(),
+            &GitGenericOpts {
+                mirror: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let events = git_generic.listen().await.unwrap();
+        let baseline = commits(&events);
+        assert_eq!(baseline.len(), 1);
+        assert!(git2::Repository::open(&baseline[0].path).unwrap().is_bare());
+
+        write(&file_path, "Mirrored").unwrap();
+        git_commit_all(&tempdir, "mirrored").await.unwrap();
+        let head = git_rev_parse_head(&tempdir).await.unwrap();
+
+        let events = git_generic.listen().await.unwrap();
+        let events = commits(&events);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].commit, head);
+
+        let mut worktree = temp_dir();
+        worktree.push(uuid::Uuid::new_v4().to_string());
+        events[0].materialize(&worktree).await.unwrap();
+        let mut materialized = worktree.clone();
+        materialized.push("readme.md");
+        assert_eq!(std::fs::read_to_string(materialized).unwrap(), "Mirrored");
+
+        remove_dir_all(worktree).await.unwrap();
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shallow_clone_finds_every_new_commit() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        for i in 0..3 {
+            write(&file_path, format!("History {i}")).unwrap();
+            git_commit_all(&tempdir, format!("history {i}"))
+                .await
+                .unwrap();
+        }
+
+        let (_daemon, url) = git_daemon(&tempdir).await.unwrap();
+        let mut git_generic = GitGeneric::with_opts(
+            url,
+            &GitGenericOpts {
+                depth: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let events = git_generic.listen().await.unwrap();
+        let baseline = commits(&events);
+        assert_eq!(baseline.len(), 1);
+        assert!(git2::Repository::open(&baseline[0].path)
+            .unwrap()
+            .is_shallow());
+
+        let mut expected = Vec::new();
+        for i in 0..3 {
+            write(&file_path, format!("New {i}")).unwrap();
+            git_commit_all(&tempdir, format!("new {i}")).await.unwrap();
+            expected.push(git_rev_parse_head(&tempdir).await.unwrap());
+        }
+
+        let events = git_generic.listen().await.unwrap();
+        let commits = commits(&events)
+            .into_iter()
+            .map(|e| e.commit.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(commits, expected);
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shallow_clone_takes_existing_tags_as_baseline() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        for i in 0..10 {
+            write(&file_path, format!("History {i}")).unwrap();
+            git_commit_all(&tempdir, format!("history {i}"))
+                .await
+                .unwrap();
+        }
+        git(&tempdir, &["tag", "old", "HEAD~7"]).await.unwrap();
+
+        let (_daemon, url) = git_daemon(&tempdir).await.unwrap();
+        let mut git_generic = GitGeneric::with_opts(
+            url,
+            &GitGenericOpts {
+                depth: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        git_generic.listen().await.unwrap();
+        let events = git_generic.listen().await.unwrap();
+        assert!(tag_events(&events).is_empty(), "{:?}", events);
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shallow_clone_stays_shallow_when_tagging_old_commits() {
+        let tempdir = git_init().await.unwrap();
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        for i in 0..10 {
+            write(&file_path, format!("History {i}")).unwrap();
+            git_commit_all(&tempdir, format!("history {i}"))
+                .await
+                .unwrap();
+        }
+
+        let (_daemon, url) = git_daemon(&tempdir).await.unwrap();
+        let mut git_generic = GitGeneric::with_opts(
+            url,
+            &GitGenericOpts {
+                depth: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let events = git_generic.listen().await.unwrap();
+        let path = commits(&events)[0].path.clone();
+
+        git(&tempdir, &["tag", "late", "HEAD~7"]).await.unwrap();
+        let events = git_generic.listen().await.unwrap();
+        let tags = tag_events(&events);
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "late");
+
+        assert!(git2::Repository::open(&path).unwrap().is_shallow());
+        let count = git(&path, &["rev-list", "--all", "--count"]).await.unwrap();
+        assert_eq!(count, "2");
+
+        remove_dir_all(tempdir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shallow_clone_resumes_from_stored_progress() {
+        let tempdir = git_init().await.unwrap();
+        let store_path = tempdir.with_extension("progress.json");
+
+        let mut file_path = tempdir.clone();
+        file_path.push("readme.md");
+        for i in 0..3 {
+            write(&file_path, format!("History {i}")).unwrap();
+            git_commit_all(&tempdir, format!("history {i}"))
+                .await
+                .unwrap();
+        }
+
+        let (_daemon, url) = git_daemon(&tempdir).await.unwrap();
+        let listener = || {
+            GitGeneric::with_opts(
+                &url,
+                &GitGenericOpts {
+                    depth: Some(1),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .set_progress_store(Arc::new(FileProgressStore::new(&store_path)))
+        };
+        let mut git = listener();
+        let events = git.listen().await.unwrap();
+        assert_eq!(commits(&events).len(), 1);
+        git.processed(&events).await.unwrap();
+
+        // Pushed while nothing was listening, the new shallow clone doesn't contain the progress
+        let mut expected = Vec::new();
+        for i in 0..2 {
+            write(&file_path, format!("New {i}")).unwrap();
+            git_commit_all(&tempdir, format!("new {i}")).await.unwrap();
+            expected.push(git_rev_parse_head(&tempdir).await.unwrap());
+        }
+
+        let events = listener().listen().await.unwrap();
+        assert!(matches!(events[0], GitEvent::Repository(_)));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, GitEvent::RefRewritten(_))));
+        let commits = commits(&events)
+            .into_iter()
+            .map(|e| e.commit.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(commits, expected);
+
+        remove_dir_all(tempdir).await.unwrap();
+        tokio::fs::remove_file(store_path).await.unwrap();
+    }
+
+    fn commits(events: &[GitEvent]) -> Vec<&CommitEvent> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                GitEvent::Commit(c) => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn tag_events(events: &[GitEvent]) -> Vec<&TagEvent> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                GitEvent::Tag(t) => Some(t),
+                _ => None,
+            })
+            .collect()
+    }
+
     async fn git_init() -> eyre::Result<PathBuf> {
         let mut tempdir = temp_dir();
         tempdir.push(uuid::Uuid::new_v4().to_string());
 
         create_dir_all(&tempdir).await.unwrap();
         let output = tokio::process::Command::new("git")
-            .args(&["init", tempdir.to_str().unwrap()])
+            .args(["init", tempdir.to_str().unwrap()])
             .output()
             .await?;
         println!("{}", std::str::from_utf8(output.stdout.as_slice()).unwrap());
@@ -240,9 +1308,9 @@ mod tests {
         Ok(tempdir)
     }
 
-    async fn git_commit_all(dir: &PathBuf, message: impl Into<String>) -> eyre::Result<()> {
+    async fn git_commit_all(dir: &Path, message: impl Into<String>) -> eyre::Result<()> {
         let output = tokio::process::Command::new("git")
-            .args(&["add", "."])
+            .args(["add", "."])
             .current_dir(dir)
             .output()
             .await
@@ -250,7 +1318,11 @@ mod tests {
         info!("{}", std::str::from_utf8(output.stdout.as_slice()).unwrap());
 
         let output = tokio::process::Command::new("git")
-            .args(&["commit", "-m", &message.into()])
+            .args(["commit", "-m", &message.into()])
+            .env("GIT_AUTHOR_NAME", "gitevents")
+            .env("GIT_AUTHOR_EMAIL", "gitevents@example.com")
+            .env("GIT_COMMITTER_NAME", "gitevents")
+            .env("GIT_COMMITTER_EMAIL", "gitevents@example.com")
             .current_dir(dir)
             .output()
             .await
@@ -259,4 +1331,66 @@ mod tests {
 
         Ok(())
     }
+
+    /// Serves `dir` over the git protocol, libgit2 only fetches shallow through a real transport.
+    async fn git_daemon(dir: &Path) -> eyre::Result<(tokio::process::Child, String)> {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?
+            .local_addr()?
+            .port();
+        let base = dir.parent().unwrap();
+
+        // `git daemon` forks the actual daemon, spawn it directly so it dies with the test
+        let mut daemon_bin = PathBuf::from(git(dir, &["--exec-path"]).await?);
+        daemon_bin.push("git-daemon");
+
+        let daemon = tokio::process::Command::new(daemon_bin)
+            .args([
+                "--export-all",
+                "--reuseaddr",
+                "--listen=127.0.0.1",
+                &format!("--port={}", port),
+                &format!("--base-path={}", base.display()),
+                dir.to_str().unwrap(),
+            ])
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        for _ in 0..50 {
+            if tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .is_ok()
+            {
+                let name = dir.file_name().unwrap().to_str().unwrap();
+                return Ok((daemon, format!("git://127.0.0.1:{}/{}", port, name)));
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        eyre::bail!("git daemon didn't start")
+    }
+
+    async fn git_rev_parse_head(dir: &Path) -> eyre::Result<String> {
+        git(dir, &["rev-parse", "HEAD"]).await
+    }
+
+    async fn git(dir: &Path, args: &[&str]) -> eyre::Result<String> {
+        let output = tokio::process::Command::new("git")
+            .args(args)
+            .env("GIT_COMMITTER_NAME", "gitevents")
+            .env("GIT_COMMITTER_EMAIL", "gitevents@example.com")
+            .current_dir(dir)
+            .output()
+            .await?;
+        eyre::ensure!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            std::str::from_utf8(output.stderr.as_slice())?
+        );
+
+        Ok(std::str::from_utf8(output.stdout.as_slice())?
+            .trim()
+            .to_string())
+    }
 }
@@ -1,17 +1,246 @@
+mod credentials;
+mod diff;
+mod error;
 pub mod generic;
+mod native;
 pub mod simulated;
+mod worktree;
 
-use std::path::PathBuf;
+pub use credentials::Credentials;
+pub use error::GitError;
+
+use std::path::{Path, PathBuf};
 
 use async_trait::async_trait;
 
+/// Something which happened in a watched repository.
+///
+/// New kinds of events may be added, so matches need a wildcard arm for the ones a handler
+/// doesn't care about.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum GitEvent {
+    /// The repository itself changed, e.g. it was cloned for the first time.
+    Repository(RepositoryEvent),
+    /// A new commit on a watched branch.
+    Commit(CommitEvent),
+    /// A tag was created, moved or deleted.
+    Tag(TagEvent),
+    /// A watched branch was force-pushed, its previous tip is no longer part of its history.
+    RefRewritten(RefRewrittenEvent),
+    /// A branch appeared on the remote.
+    BranchCreated(BranchEvent),
+    /// A branch disappeared from the remote.
+    BranchDeleted(BranchEvent),
+}
+
+impl GitEvent {
+    /// The url of the repository the event happened in.
+    pub fn url(&self) -> &str {
+        match self {
+            Self::Repository(e) => &e.url,
+            Self::Commit(e) => &e.url,
+            Self::Tag(e) => &e.url,
+            Self::RefRewritten(e) => &e.url,
+            Self::BranchCreated(e) | Self::BranchDeleted(e) => &e.url,
+        }
+    }
+
+    /// The local repository the event was computed from.
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Repository(e) => &e.path,
+            Self::Commit(e) => &e.path,
+            Self::Tag(e) => &e.path,
+            Self::RefRewritten(e) => &e.path,
+            Self::BranchCreated(e) | Self::BranchDeleted(e) => &e.path,
+        }
+    }
+
+    /// The full name of the ref the event is about, e.g. `refs/heads/main` or `refs/tags/v1`.
+    pub fn reference(&self) -> Option<String> {
+        match self {
+            Self::Repository(_) => None,
+            Self::Commit(e) => Some(e.reference.clone()),
+            Self::Tag(e) => Some(format!("refs/tags/{}", e.name)),
+            Self::RefRewritten(e) => Some(e.reference.clone()),
+            Self::BranchCreated(e) | Self::BranchDeleted(e) => Some(e.reference.clone()),
+        }
+    }
+
+    /// The commit the event is about, the new tip for rewrites.
+    pub fn commit(&self) -> Option<&str> {
+        match self {
+            Self::Repository(_) => None,
+            Self::Commit(e) => Some(&e.commit),
+            Self::Tag(e) => Some(&e.commit),
+            Self::RefRewritten(e) => Some(&e.new),
+            Self::BranchCreated(e) | Self::BranchDeleted(e) => Some(&e.commit),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum RepositoryChange {
+    /// The repository was cloned, the events following it are the baseline.
+    Cloned,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RepositoryEvent {
+    pub url: String,
+    pub change: RepositoryChange,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommitEvent {
+    pub commit: String,
+    /// The ref the commit was found on, e.g. `refs/heads/main`.
+    pub reference: String,
+    /// The url of the watched repository.
+    pub url: String,
+    pub author: GitSignature,
+    pub committer: GitSignature,
+    /// The full commit message.
+    pub message: String,
+    /// The first line of the commit message.
+    pub summary: String,
+    /// The parent commits, more than one for merges.
+    pub parents: Vec<String>,
+    /// The files changed compared to the first parent.
+    pub changes: Vec<FileChange>,
+    /// The local repository, either a checkout of the default branch or a bare mirror.
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileChange {
+    /// The path relative to the repository root, the old path for deleted files.
+    pub path: PathBuf,
+    /// The path before a rename.
+    pub old_path: Option<PathBuf>,
+    pub kind: ChangeKind,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GitSignature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the unix epoch.
+    pub time: i64,
+    /// The timezone of `time`, as minutes east of UTC.
+    pub offset_minutes: i32,
+}
+
+impl From<git2::Signature<'_>> for GitSignature {
+    fn from(signature: git2::Signature<'_>) -> Self {
+        Self {
+            name: String::from_utf8_lossy(signature.name_bytes()).into_owned(),
+            email: String::from_utf8_lossy(signature.email_bytes()).into_owned(),
+            time: signature.when().seconds(),
+            offset_minutes: signature.when().offset_minutes(),
+        }
+    }
+}
+
+impl CommitEvent {
+    /// Checks out the files of this commit into `dest`, which is created if missing.
+    ///
+    /// Needed when watching a bare mirror, or a branch other than the checked out one.
+    pub async fn materialize(&self, dest: impl AsRef<Path>) -> eyre::Result<()> {
+        worktree::materialize(&self.path, &self.commit, dest.as_ref()).await
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct GitEvent {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RefRewrittenEvent {
+    /// The rewritten ref, e.g. `refs/heads/main`.
+    pub reference: String,
+    pub url: String,
+    /// The tip before the rewrite, the last commit which was reported for this ref.
+    pub old: String,
+    /// The tip after the rewrite, progress continues from here.
+    pub new: String,
+    /// The last commit shared by the old and new history, `None` if they're unrelated.
+    pub merge_base: Option<String>,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BranchEvent {
+    /// The branch ref on the remote, e.g. `refs/heads/feature/x`.
+    pub reference: String,
+    pub url: String,
+    /// The tip of the branch, or the last known tip of a deleted branch.
+    pub commit: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum TagChange {
+    Created,
+    /// The tag now points somewhere else, `previous` is the commit it used to point at.
+    Moved {
+        previous: String,
+    },
+    Deleted,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TagEvent {
+    /// The tag name without the `refs/tags/` prefix.
+    pub name: String,
+    pub url: String,
+    pub change: TagChange,
+    /// The commit the tag points at, or pointed at before it was deleted.
     pub commit: String,
+    /// The annotation message, `None` for lightweight tags.
+    pub message: Option<String>,
     pub path: PathBuf,
 }
 
 #[async_trait]
 pub trait GitProvider {
-    async fn listen(&mut self) -> eyre::Result<Option<GitEvent>>;
+    /// Returns every event which happened since the last call, oldest first.
+    ///
+    /// The events aren't considered processed until they're passed to
+    /// [`GitProvider::processed`]. The executor does so once its handlers are done with them,
+    /// anything else calling `listen` directly has to call `processed` itself, or a provider
+    /// persisting its progress emits the events again after a restart.
+    async fn listen(&mut self) -> eyre::Result<Vec<GitEvent>>;
+
+    /// Called with the events of a successful `listen` once every handler is done with them,
+    /// in the order they were listened to. Providers which persist how far they got should only
+    /// do so here, and only up to the events they're given, so events which were still being
+    /// handled when the process stopped are emitted again after a restart.
+    async fn processed(&mut self, _events: &[GitEvent]) -> eyre::Result<()> {
+        Ok(())
+    }
 }
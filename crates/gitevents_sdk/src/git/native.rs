@@ -0,0 +1,237 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use git2::build::RepoBuilder;
+use git2::{FetchOptions, RemoteCallbacks, Repository};
+
+use super::generic::GitGenericOpts;
+use super::{Credentials, GitError};
+
+const REMOTE: &str = "origin";
+/// Tags are force fetched through an explicit refspec instead of being auto-followed, which never
+/// updates a tag that already exists locally. Combined with pruning, moved and deleted tags are
+/// mirrored as well.
+const TAGS_REFSPEC: &str = "+refs/tags/*:refs/tags/*";
+const MIRROR_REFSPEC: &str = "+refs/heads/*:refs/heads/*";
+
+type Lines = Arc<Mutex<Vec<String>>>;
+
+fn remote_callbacks<'a>(
+    operation: &'static str,
+    stderr: Lines,
+    credentials: &Credentials,
+) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+
+    callbacks.credentials(credentials.callback());
+
+    callbacks.sideband_progress(move |line| {
+        let line = String::from_utf8_lossy(line);
+        // Progress counters are redrawn using carriage returns, only keep the final state.
+        for line in line.split(['\r', '\n']).map(str::trim) {
+            if !line.is_empty() {
+                tracing::debug!(line = line, "remote: git {}", operation);
+                stderr.lock().unwrap().push(line.to_string());
+            }
+        }
+        true
+    });
+
+    callbacks.transfer_progress(move |progress| {
+        tracing::trace!(
+            received_objects = progress.received_objects(),
+            total_objects = progress.total_objects(),
+            received_bytes = progress.received_bytes(),
+            "progress: git {}",
+            operation
+        );
+        true
+    });
+
+    callbacks
+}
+
+fn fetch_options<'a>(
+    operation: &'static str,
+    stderr: Lines,
+    credentials: &Credentials,
+) -> FetchOptions<'a> {
+    let mut fo = FetchOptions::new();
+    fo.remote_callbacks(remote_callbacks(operation, stderr, credentials));
+    fo
+}
+
+/// Runs a blocking git2 operation, turning its failure into a [`GitError`] carrying everything
+/// the remote reported while it ran.
+async fn run<F>(
+    command: String,
+    operation: &'static str,
+    credentials: &Credentials,
+    f: F,
+) -> eyre::Result<()>
+where
+    F: FnOnce(FetchOptions<'static>) -> Result<(), git2::Error> + Send + 'static,
+{
+    let stderr: Lines = Default::default();
+
+    let lines = stderr.clone();
+    let credentials = credentials.clone();
+    let res = tokio::task::spawn_blocking(move || f(fetch_options(operation, lines, &credentials)))
+        .await?;
+    let stderr = std::mem::take(&mut *stderr.lock().unwrap());
+
+    match res {
+        Ok(()) => {
+            tracing::debug!("git {} finished", operation);
+            Ok(())
+        }
+        Err(e) => {
+            let err = GitError::new(command, e, stderr);
+            tracing::debug!(error = err.to_string(), "git {} failed", operation);
+            Err(err.into())
+        }
+    }
+}
+
+/// Clones `url` into `path`, leaving a working tree checked out at the default branch.
+///
+/// With `mirror` set a bare repository is created instead, with the remote's branches and tags
+/// stored directly under `refs/heads` and `refs/tags`.
+pub async fn clone(url: &str, path: &Path, opts: &GitGenericOpts) -> eyre::Result<()> {
+    let mut command = String::from("git clone");
+    if opts.mirror {
+        command.push_str(" --mirror");
+    }
+    if let Some(depth) = opts.depth {
+        command.push_str(&format!(" --depth {}", depth));
+    }
+    command.push_str(&format!(" {} {}", url, path.display()));
+
+    let url = url.to_string();
+    let path = path.to_path_buf();
+    let (mirror, depth) = (opts.mirror, opts.depth);
+
+    run(command, "clone", &opts.credentials, move |mut fo| {
+        if let Some(depth) = depth {
+            fo.depth(depth.try_into().unwrap_or(i32::MAX));
+        }
+
+        let mut builder = RepoBuilder::new();
+        builder.fetch_options(fo).bare(mirror);
+        // Tags are cloned through the same refspec they're pulled with, otherwise the first pull
+        // fetches every tag which wasn't auto-followed, as if it had just been created.
+        builder.remote_create(move |repo, name, url| {
+            match mirror {
+                true => repo.remote_with_fetch(name, url, MIRROR_REFSPEC)?,
+                false => repo.remote(name, url)?,
+            };
+            repo.remote_add_fetch(name, TAGS_REFSPEC)?;
+            // Added refspecs only apply to remotes loaded afterwards
+            repo.find_remote(name)
+        });
+        builder.clone(&url, &path)?;
+
+        Ok(())
+    })
+    .await
+}
+
+/// Fetches every branch and tag of the remote, pruning the ones which no longer exist.
+///
+/// Shallow clones fetch their tags separately with `tags_depth`, a tag on an old commit would
+/// otherwise pull in the whole history below it.
+fn fetch(
+    repo: &Repository,
+    mut fo: FetchOptions<'_>,
+    tags_depth: Option<u32>,
+) -> Result<(), git2::Error> {
+    let mut remote = repo.find_remote(REMOTE)?;
+    let mut refspecs = remote
+        .fetch_refspecs()?
+        .iter()
+        .flatten()
+        .filter(|r| *r != TAGS_REFSPEC)
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    fo.prune(git2::FetchPrune::On);
+    fo.download_tags(git2::AutotagOption::None);
+    if !repo.is_shallow() {
+        refspecs.push(TAGS_REFSPEC.into());
+        return remote.fetch(&refspecs, Some(&mut fo), None);
+    }
+
+    remote.fetch(&refspecs, Some(&mut fo), None)?;
+    fo.depth(tags_depth.unwrap_or(1).try_into().unwrap_or(i32::MAX));
+    remote.fetch(&[TAGS_REFSPEC], Some(&mut fo), None)
+}
+
+/// Fetches the remote and moves the checked out branch onto its upstream.
+///
+/// The checkout is owned by gitevents, so local changes are discarded in the same way as a
+/// `git reset --hard @{u}` would. Mirrors have no checkout, for them this is only a fetch.
+///
+/// Shallow clones fetch their branches without a depth, the remote then sends every commit down to
+/// the shallow boundary, so the full range since the last fetch can be walked. Their tags are
+/// fetched with the clone's depth.
+pub async fn pull(path: &Path, opts: &GitGenericOpts) -> eyre::Result<()> {
+    let command = format!("git pull {}", REMOTE);
+    let path = path.to_path_buf();
+    let depth = opts.depth;
+
+    run(command, "pull", &opts.credentials, move |fo| {
+        let repo = Repository::open(&path)?;
+        fetch(&repo, fo, depth)?;
+
+        if repo.is_bare() {
+            return Ok(());
+        }
+
+        let head = repo.head()?;
+        let branch = head
+            .shorthand()
+            .ok_or_else(|| git2::Error::from_str("HEAD is not pointing at a valid branch name"))?;
+        let upstream = repo
+            .find_branch(branch, git2::BranchType::Local)?
+            .upstream()?
+            .get()
+            .peel_to_commit()?;
+
+        if head.target() != Some(upstream.id()) {
+            repo.reset(upstream.as_object(), git2::ResetType::Hard, None)?;
+        }
+
+        Ok(())
+    })
+    .await
+}
+
+/// Deepens a shallow clone until `commit` is part of its history, e.g. the last processed commit
+/// of a previous run. The depth is doubled with every fetch, until the clone is complete if
+/// `commit` is no longer reachable on the remote.
+pub async fn deepen(path: &Path, opts: &GitGenericOpts, commit: git2::Oid) -> eyre::Result<()> {
+    let mut depth = opts.depth.unwrap_or(1).max(1);
+
+    loop {
+        let repo = Repository::open(path)?;
+        if !repo.is_shallow() || repo.find_commit(commit).is_ok() {
+            return Ok(());
+        }
+
+        // libgit2 fetches the full history for a depth of `i32::MAX`
+        depth = depth.saturating_mul(2).min(i32::MAX as u32);
+        tracing::debug!(
+            commit = commit.to_string(),
+            depth = depth,
+            "deepening clone"
+        );
+
+        let command = format!("git fetch --depth {} {}", depth, REMOTE);
+        let path = path.to_path_buf();
+        run(command, "fetch", &opts.credentials, move |mut fo| {
+            fo.depth(depth as i32);
+            fetch(&Repository::open(&path)?, fo, Some(depth))
+        })
+        .await?;
+    }
+}
@@ -22,12 +22,18 @@ impl GitSimulated {
     }
 }
 
+impl Default for GitSimulated {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl GitProvider for GitSimulated {
-    async fn listen(&mut self) -> eyre::Result<Option<GitEvent>> {
+    async fn listen(&mut self) -> eyre::Result<Vec<GitEvent>> {
         let mutex = self.mutex.lock().await;
         let event = self.events.pop();
         drop(mutex);
-        return Ok(event);
+        Ok(event.into_iter().collect())
     }
 }
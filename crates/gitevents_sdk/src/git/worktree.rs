@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use git2::build::CheckoutBuilder;
+use git2::Repository;
+
+/// Writes the files of `commit` in the repository at `repo` into `dest`.
+///
+/// Works for bare mirrors as well as checkouts, the repository's own working tree and index are
+/// left untouched.
+pub async fn materialize(repo: &Path, commit: &str, dest: &Path) -> eyre::Result<()> {
+    let repo = repo.to_path_buf();
+    let commit = commit.to_string();
+    let dest = dest.to_path_buf();
+
+    tokio::task::spawn_blocking(move || -> eyre::Result<()> {
+        let repo = Repository::open(&repo)?;
+        let commit = repo.find_commit(git2::Oid::from_str(&commit)?)?;
+
+        std::fs::create_dir_all(&dest)?;
+        repo.checkout_tree(
+            commit.as_object(),
+            Some(
+                CheckoutBuilder::new()
+                    .target_dir(&dest)
+                    .update_index(false)
+                    .force(),
+            ),
+        )?;
+
+        tracing::trace!(
+            commit = commit.id().to_string(),
+            dest = dest.display().to_string(),
+            "materialized worktree"
+        );
+
+        Ok(())
+    })
+    .await?
+}
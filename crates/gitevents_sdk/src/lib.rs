@@ -1,9 +1,18 @@
 mod action_event_handler;
 pub mod builder;
+#[cfg(feature = "cloudevents")]
+pub mod cloudevents;
 pub mod cron;
+#[cfg(feature = "serde")]
+pub mod envelope;
 pub mod events;
+pub mod filter;
 pub mod git;
-mod storage;
+pub mod progress;
+pub mod retry;
+#[cfg(any(feature = "webhook", feature = "nats"))]
+pub mod sinks;
+pub mod storage;
 
 use self::builder::Builder;
 
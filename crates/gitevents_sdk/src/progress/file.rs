@@ -0,0 +1,118 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use tokio::sync::Mutex;
+
+use super::{Progress, ProgressStore};
+
+/// Stores progress in a JSON file, keyed by repository url and then ref:
+///
+/// ```json
+/// {
+///   "git@github.com:kjuulh/gitevents.git": {
+///     "refs/heads/main": "4b825dc642cb6eb9a060e54bf8d69288fbee4904"
+///   }
+/// }
+/// ```
+///
+/// The file is replaced atomically on every store, so a crash never leaves it half written. It
+/// shouldn't be shared between processes.
+#[derive(Debug)]
+pub struct FileProgressStore {
+    path: PathBuf,
+    /// Serializes read-modify-write cycles of repositories sharing the store.
+    lock: Mutex<()>,
+}
+
+impl FileProgressStore {
+    /// The file and its parent directories are created on the first store.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    async fn read(&self) -> eyre::Result<Map<String, Value>> {
+        match tokio::fs::read(&self.path).await {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Map::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl ProgressStore for FileProgressStore {
+    async fn load(&self, repo: &str) -> eyre::Result<Progress> {
+        let _lock = self.lock.lock().await;
+
+        match self.read().await?.remove(repo) {
+            Some(progress) => Ok(serde_json::from_value(progress)?),
+            None => Ok(Progress::new()),
+        }
+    }
+
+    async fn store(&self, repo: &str, progress: &Progress) -> eyre::Result<()> {
+        let _lock = self.lock.lock().await;
+
+        let mut repos = self.read().await?;
+        repos.insert(repo.to_string(), serde_json::to_value(progress)?);
+
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let tmp = tmp_path(&self.path);
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(&repos)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+
+        tracing::trace!(
+            repo = repo,
+            path = self.path.display().to_string(),
+            "stored progress"
+        );
+
+        Ok(())
+    }
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use crate::progress::{Progress, ProgressStore};
+
+    use super::FileProgressStore;
+
+    #[tokio::test]
+    async fn test_progress_survives_a_new_store() {
+        let mut dir = temp_dir();
+        dir.push(uuid::Uuid::new_v4().to_string());
+        let path = dir.join("progress.json");
+
+        let store = FileProgressStore::new(&path);
+        assert!(store.load("repo-a").await.unwrap().is_empty());
+
+        let a = Progress::from([("refs/heads/main".to_string(), "abc".to_string())]);
+        let b = Progress::from([("refs/heads/dev".to_string(), "def".to_string())]);
+        store.store("repo-a", &a).await.unwrap();
+        store.store("repo-b", &b).await.unwrap();
+
+        let store = FileProgressStore::new(&path);
+        assert_eq!(store.load("repo-a").await.unwrap(), a);
+        assert_eq!(store.load("repo-b").await.unwrap(), b);
+
+        store.store("repo-a", &Progress::new()).await.unwrap();
+        assert!(store.load("repo-a").await.unwrap().is_empty());
+        assert_eq!(store.load("repo-b").await.unwrap(), b);
+
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use super::{Progress, ProgressStore};
+
+/// Keeps progress for the lifetime of the process only, a restart starts from the tips of the
+/// watched branches again.
+#[derive(Debug, Default)]
+pub struct MemoryProgressStore {
+    repos: Mutex<HashMap<String, Progress>>,
+}
+
+impl MemoryProgressStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ProgressStore for MemoryProgressStore {
+    async fn load(&self, repo: &str) -> eyre::Result<Progress> {
+        Ok(self
+            .repos
+            .lock()
+            .await
+            .get(repo)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn store(&self, repo: &str, progress: &Progress) -> eyre::Result<()> {
+        self.repos
+            .lock()
+            .await
+            .insert(repo.to_string(), progress.clone());
+        Ok(())
+    }
+}
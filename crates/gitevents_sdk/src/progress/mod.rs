@@ -0,0 +1,26 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+pub mod file;
+pub mod memory;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+/// The last processed commit per ref, e.g. `refs/heads/main` -> `4b825dc...`.
+pub type Progress = HashMap<String, String>;
+
+/// Keeps track of how far each repository has been processed, so a restarted listener resumes
+/// where it stopped and emits the commits pushed while it was down.
+///
+/// Repositories are identified by their url, so one store can be shared by many of them.
+#[async_trait]
+pub trait ProgressStore {
+    /// The stored progress of a repository, empty if it has never been stored.
+    async fn load(&self, repo: &str) -> eyre::Result<Progress>;
+    /// Replaces the stored progress of a repository, refs missing from `progress` are removed.
+    async fn store(&self, repo: &str, progress: &Progress) -> eyre::Result<()>;
+}
+
+pub type DynProgressStore = Arc<dyn ProgressStore + Send + Sync>;
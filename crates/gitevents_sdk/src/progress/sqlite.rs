@@ -0,0 +1,110 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection};
+
+use super::{Progress, ProgressStore};
+
+/// Stores progress in a SQLite database, one row per repository and ref.
+pub struct SqliteProgressStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteProgressStore {
+    /// Opens or creates the database at `path`, and creates the `progress` table if needed.
+    pub fn open(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn with_connection(conn: Connection) -> eyre::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS progress (
+                repo TEXT NOT NULL,
+                reference TEXT NOT NULL,
+                commit_id TEXT NOT NULL,
+                PRIMARY KEY (repo, reference)
+            )",
+        )?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    async fn run<T, F>(&self, f: F) -> eyre::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let res = tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await??;
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl ProgressStore for SqliteProgressStore {
+    async fn load(&self, repo: &str) -> eyre::Result<Progress> {
+        let repo = repo.to_string();
+        self.run(move |conn| {
+            let mut stmt =
+                conn.prepare("SELECT reference, commit_id FROM progress WHERE repo = ?1")?;
+            let rows = stmt.query_map(params![repo], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect()
+        })
+        .await
+    }
+
+    async fn store(&self, repo: &str, progress: &Progress) -> eyre::Result<()> {
+        let repo = repo.to_string();
+        let progress = progress.clone();
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM progress WHERE repo = ?1", params![repo])?;
+            for (reference, commit) in &progress {
+                tx.execute(
+                    "INSERT INTO progress (repo, reference, commit_id) VALUES (?1, ?2, ?3)",
+                    params![repo, reference, commit],
+                )?;
+            }
+            tx.commit()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use crate::progress::{Progress, ProgressStore};
+
+    use super::SqliteProgressStore;
+
+    #[tokio::test]
+    async fn test_progress_survives_a_new_store() {
+        let mut path = temp_dir();
+        path.push(format!("{}.sqlite", uuid::Uuid::new_v4()));
+
+        let store = SqliteProgressStore::open(&path).unwrap();
+        assert!(store.load("repo-a").await.unwrap().is_empty());
+
+        let a = Progress::from([
+            ("refs/heads/main".to_string(), "abc".to_string()),
+            ("refs/heads/dev".to_string(), "def".to_string()),
+        ]);
+        store.store("repo-a", &a).await.unwrap();
+        drop(store);
+
+        let store = SqliteProgressStore::open(&path).unwrap();
+        assert_eq!(store.load("repo-a").await.unwrap(), a);
+        assert!(store.load("repo-b").await.unwrap().is_empty());
+
+        let main = Progress::from([("refs/heads/main".to_string(), "ghi".to_string())]);
+        store.store("repo-a", &main).await.unwrap();
+        assert_eq!(store.load("repo-a").await.unwrap(), main);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
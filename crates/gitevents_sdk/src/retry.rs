@@ -0,0 +1,139 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::Future;
+use rand::Rng;
+
+use crate::events::EventRequest;
+
+/// How a handler is retried when it returns an error, also used by the built-in handlers for
+/// their own retries.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// How many times the handler is called before the event is dead-lettered, at least once.
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled for every following retry.
+    pub backoff: Duration,
+    /// The upper bound of the delay between two attempts.
+    pub max_backoff: Duration,
+    /// The fraction of the delay which is randomized, from `0.0` (none) to `1.0` (anywhere
+    /// between zero and the delay), so handlers failing together don't retry in lockstep. Values
+    /// outside that range are clamped, and `NaN` or infinite values disable the jitter.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    /// A single attempt, failures are dead-lettered right away.
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            jitter: 0.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay after the failed `attempt`, counting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff);
+
+        // `mul_f64` panics on `NaN`, which `clamp` lets through
+        if !self.jitter.is_finite() {
+            return delay;
+        }
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>())
+    }
+}
+
+/// An event a handler failed to process.
+#[derive(Debug)]
+pub struct DeadLetter {
+    /// The id the failing handler was registered with.
+    pub handler: uuid::Uuid,
+    pub req: EventRequest,
+    /// How many times the handler was called with the event.
+    pub attempts: u32,
+    /// The error of the last attempt, `{:?}` prints the whole chain.
+    pub error: eyre::Report,
+}
+
+/// Receives the events a handler gave up on, e.g. to store them for a later replay.
+///
+/// Implemented for async closures taking a [`DeadLetter`].
+#[async_trait]
+pub trait DeadLetterHandler {
+    async fn handle(&self, letter: DeadLetter) -> eyre::Result<()>;
+}
+
+pub type DynDeadLetterHandler = Arc<dyn DeadLetterHandler + Send + Sync>;
+
+#[async_trait]
+impl<F, Fut> DeadLetterHandler for F
+where
+    F: Fn(DeadLetter) -> Fut + Send + Sync,
+    Fut: Future<Output = eyre::Result<()>> + Send,
+{
+    async fn handle(&self, letter: DeadLetter) -> eyre::Result<()> {
+        self(letter).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn test_exponential_backoff_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            jitter: 0.0,
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(4), Duration::from_secs(5));
+        assert_eq!(policy.delay(100), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_the_delay() {
+        let policy = RetryPolicy {
+            backoff: Duration::from_secs(4),
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn test_invalid_jitter_is_ignored() {
+        for jitter in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let policy = RetryPolicy {
+                backoff: Duration::from_secs(4),
+                jitter,
+                ..Default::default()
+            };
+
+            assert_eq!(policy.delay(1), Duration::from_secs(4));
+        }
+    }
+}
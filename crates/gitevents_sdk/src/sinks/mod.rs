@@ -0,0 +1,32 @@
+//! Built-in handlers forwarding events to other services.
+
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+use crate::cloudevents::{CloudEvent, HttpMessage, Mode};
+use crate::envelope::Envelope;
+use crate::git::GitEvent;
+
+/// How a sink encodes the events it forwards.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// The versioned JSON [`Envelope`].
+    #[default]
+    Envelope,
+    /// A [`CloudEvent`], laid out according to the mode.
+    CloudEvents(Mode),
+}
+
+impl Format {
+    pub fn encode(&self, event: &GitEvent) -> eyre::Result<HttpMessage> {
+        match self {
+            Self::Envelope => Ok(HttpMessage {
+                headers: vec![("content-type".into(), "application/json".into())],
+                body: serde_json::to_vec(&Envelope::new(event.clone()))?,
+            }),
+            Self::CloudEvents(mode) => CloudEvent::new(event)?.to_http(*mode),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use async_nats::jetstream;
+use async_nats::HeaderMap;
+use async_trait::async_trait;
+use tokio::sync::OnceCell;
+
+use crate::events::{EventHandler, EventRequest, EventResponse};
+use crate::git::GitEvent;
+
+use super::Format;
+
+#[derive(Clone, Debug)]
+pub struct NatsOpts {
+    /// The subject events are published to. `{repo}` is replaced with the repository name,
+    /// `{branch}` with the branch or tag name, `{kind}` with the event kind (`commit`, `tag`, ...).
+    ///
+    /// Characters which aren't allowed within a subject token (`.`, `*`, `>` and whitespace) are
+    /// replaced with `_`, as are missing values, e.g. `{branch}` for a clone.
+    pub subject: String,
+    /// Publish through JetStream, and wait for the stream to acknowledge every event. The subject
+    /// has to be bound to a stream.
+    pub jetstream: bool,
+    pub format: Format,
+}
+
+impl Default for NatsOpts {
+    fn default() -> Self {
+        Self {
+            subject: "gitevents.{repo}.{branch}".into(),
+            jetstream: false,
+            format: Format::default(),
+        }
+    }
+}
+
+/// Publishes every event to NATS, the connection is established when the first event arrives.
+pub struct NatsHandler {
+    url: String,
+    opts: NatsOpts,
+    client: OnceCell<async_nats::Client>,
+}
+
+impl NatsHandler {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_opts(url, &NatsOpts::default())
+    }
+
+    pub fn with_opts(url: impl Into<String>, opts: &NatsOpts) -> Self {
+        Self {
+            url: url.into(),
+            opts: opts.clone(),
+            client: OnceCell::new(),
+        }
+    }
+
+    async fn client(&self) -> eyre::Result<&async_nats::Client> {
+        self.client
+            .get_or_try_init(|| async {
+                tracing::debug!(url = self.url, "connecting to nats");
+                async_nats::connect(&self.url).await
+            })
+            .await
+            .map_err(|e| eyre::eyre!("failed to connect to nats at {}: {}", self.url, e))
+    }
+}
+
+#[async_trait]
+impl EventHandler for NatsHandler {
+    async fn handle(&self, req: EventRequest) -> eyre::Result<EventResponse> {
+        let subject = subject(&self.opts.subject, &req.git);
+        let message = self.opts.format.encode(&req.git)?;
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in message.headers {
+            headers.insert(name.as_str(), value.as_str());
+        }
+
+        let client = self.client().await?;
+        if self.opts.jetstream {
+            jetstream::new(client.clone())
+                .publish_with_headers(subject.clone(), headers, message.body.into())
+                .await?
+                .await?;
+        } else {
+            client
+                .publish_with_headers(subject.clone(), headers, message.body.into())
+                .await?;
+            client.flush().await?;
+        }
+
+        tracing::debug!(subject = subject, "published event to nats");
+
+        Ok(EventResponse::Ack)
+    }
+}
+
+fn subject(template: &str, event: &GitEvent) -> String {
+    let repo = event
+        .url()
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .map(|name| name.trim_end_matches(".git"))
+        .unwrap_or_default();
+    let reference = event.reference().unwrap_or_default();
+    let branch = reference
+        .strip_prefix("refs/heads/")
+        .or_else(|| reference.strip_prefix("refs/tags/"))
+        .unwrap_or(&reference);
+    let kind = match event {
+        GitEvent::Repository(_) => "repository",
+        GitEvent::Commit(_) => "commit",
+        GitEvent::Tag(_) => "tag",
+        GitEvent::RefRewritten(_) => "ref_rewritten",
+        GitEvent::BranchCreated(_) => "branch_created",
+        GitEvent::BranchDeleted(_) => "branch_deleted",
+    };
+
+    template
+        .replace("{repo}", &token(repo))
+        .replace("{branch}", &token(branch))
+        .replace("{kind}", kind)
+}
+
+fn token(value: &str) -> String {
+    if value.is_empty() {
+        return "_".into();
+    }
+
+    value
+        .chars()
+        .map(|c| match c {
+            '.' | '*' | '>' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Stdio;
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use crate::events::{EventHandler, EventRequest};
+    use crate::git::{BranchEvent, CommitEvent, GitEvent, RepositoryChange, RepositoryEvent};
+
+    use super::{subject, NatsHandler, NatsOpts};
+
+    fn commit(url: &str, reference: &str) -> GitEvent {
+        GitEvent::Commit(CommitEvent {
+            commit: "abc".into(),
+            reference: reference.into(),
+            url: url.into(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_subject_from_template() {
+        let template = "gitevents.{repo}.{branch}";
+
+        assert_eq!(
+            subject(
+                template,
+                &commit("git@github.com:kjuulh/gitevents.git", "refs/heads/main")
+            ),
+            "gitevents.gitevents.main"
+        );
+        assert_eq!(
+            subject(
+                template,
+                &commit("https://example.com/my.repo", "refs/heads/release/1.x")
+            ),
+            "gitevents.my_repo.release/1_x"
+        );
+        assert_eq!(
+            subject(
+                "{kind}.{repo}.{branch}",
+                &GitEvent::Repository(RepositoryEvent {
+                    url: "https://example.com/repo.git".into(),
+                    change: RepositoryChange::Cloned,
+                    path: Default::default(),
+                })
+            ),
+            "repository.repo._"
+        );
+        assert_eq!(
+            subject(
+                "{kind}",
+                &GitEvent::BranchCreated(BranchEvent {
+                    reference: "refs/heads/x".into(),
+                    url: "repo".into(),
+                    commit: "abc".into(),
+                    path: Default::default(),
+                })
+            ),
+            "branch_created"
+        );
+    }
+
+    /// Publishes to a `nats-server` started on a free port, run with
+    /// `cargo test --features nats -- --ignored` where it's installed.
+    #[tokio::test]
+    #[ignore = "needs nats-server"]
+    async fn test_publishes_to_nats_server() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let _server = tokio::process::Command::new("nats-server")
+            .args(["-a", "127.0.0.1", "-p", &port.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .expect("nats-server to be installed");
+
+        let url = format!("nats://127.0.0.1:{}", port);
+        let subscriber = loop {
+            match async_nats::connect(&url).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        };
+        let mut messages = subscriber.subscribe("gitevents.>").await.unwrap();
+        subscriber.flush().await.unwrap();
+
+        let handler = NatsHandler::with_opts(&url, &NatsOpts::default());
+        handler
+            .handle(EventRequest {
+                git: commit("https://example.com/repo.git", "refs/heads/main"),
+            })
+            .await
+            .unwrap();
+
+        let message = tokio::time::timeout(Duration::from_secs(5), messages.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.subject.as_str(), "gitevents.repo.main");
+        let body: serde_json::Value = serde_json::from_slice(&message.payload).unwrap();
+        assert_eq!(body["event"]["commit"], "abc");
+    }
+}
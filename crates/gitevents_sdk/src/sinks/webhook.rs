@@ -0,0 +1,331 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use sha2::Sha256;
+
+use crate::events::{EventHandler, EventRequest, EventResponse};
+use crate::retry::RetryPolicy;
+
+use super::Format;
+
+/// The header carrying `sha256=<hex hmac of the body>` when a secret is configured, the same
+/// scheme GitHub uses for its webhooks.
+pub const SIGNATURE_HEADER: &str = "x-gitevents-signature-256";
+
+#[derive(Clone, Debug)]
+pub struct WebhookOpts {
+    /// The timeout of a single request, including reading the response.
+    pub timeout: Duration,
+    /// How failed requests are retried before the event is given up on.
+    pub retry: RetryPolicy,
+    /// Sent with every request, e.g. an `authorization` header.
+    pub headers: Vec<(String, String)>,
+    /// Signs every request body with HMAC-SHA256, see [`SIGNATURE_HEADER`].
+    pub secret: Option<String>,
+    pub format: Format,
+}
+
+impl Default for WebhookOpts {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            retry: RetryPolicy {
+                max_attempts: 3,
+                backoff: Duration::from_millis(500),
+                max_backoff: Duration::from_secs(30),
+                jitter: 0.2,
+            },
+            headers: Vec::new(),
+            secret: None,
+            format: Format::default(),
+        }
+    }
+}
+
+/// POSTs every event to a url.
+///
+/// Requests failing on their way to the server, e.g. because the connection was refused, reset or
+/// timed out, or answered with a 5xx or 429 status are retried. Invalid requests and other
+/// responses than 2xx fail the event right away.
+pub struct WebhookHandler {
+    url: String,
+    opts: WebhookOpts,
+    client: reqwest::Client,
+}
+
+impl WebhookHandler {
+    pub fn new(url: impl Into<String>) -> eyre::Result<Self> {
+        Self::with_opts(url, &WebhookOpts::default())
+    }
+
+    pub fn with_opts(url: impl Into<String>, opts: &WebhookOpts) -> eyre::Result<Self> {
+        Ok(Self {
+            url: url.into(),
+            opts: opts.clone(),
+            client: reqwest::Client::builder().timeout(opts.timeout).build()?,
+        })
+    }
+
+    fn request(&self, headers: &[(String, String)], body: &[u8]) -> reqwest::RequestBuilder {
+        let mut request = self.client.post(&self.url);
+        for (name, value) in headers.iter().chain(&self.opts.headers) {
+            request = request.header(name, value);
+        }
+        if let Some(secret) = &self.opts.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, body));
+        }
+        request.body(body.to_vec())
+    }
+}
+
+#[async_trait]
+impl EventHandler for WebhookHandler {
+    async fn handle(&self, req: EventRequest) -> eyre::Result<EventResponse> {
+        let message = self.opts.format.encode(&req.git)?;
+        let max_attempts = self.opts.retry.max_attempts.max(1);
+
+        let mut attempt = 1;
+        loop {
+            let request = self.request(&message.headers, &message.body);
+            let (err, retryable) = match request.send().await {
+                Ok(res) if res.status().is_success() => {
+                    tracing::debug!(
+                        url = self.url,
+                        status = res.status().as_u16(),
+                        "webhook delivered"
+                    );
+                    return Ok(EventResponse::Ack);
+                }
+                Ok(res) => {
+                    let status = res.status();
+                    let retryable =
+                        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+                    (
+                        eyre::eyre!("webhook {} responded with {}", self.url, status),
+                        retryable,
+                    )
+                }
+                Err(e) => {
+                    let retryable = is_transport_error(&e);
+                    (eyre::Report::from(e), retryable)
+                }
+            };
+
+            if !retryable || attempt >= max_attempts {
+                return Err(err.wrap_err(format!(
+                    "failed to deliver event to webhook after {} attempt(s)",
+                    attempt
+                )));
+            }
+
+            let delay = self.opts.retry.delay(attempt);
+            tracing::warn!(
+                url = self.url,
+                attempt = attempt,
+                error = err.to_string(),
+                "webhook failed, retrying in {:?}",
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Whether sending failed on the way to the server, rather than the request itself being invalid,
+/// e.g. an unparsable url or header.
+fn is_transport_error(e: &reqwest::Error) -> bool {
+    if e.is_connect() || e.is_timeout() {
+        return true;
+    }
+    if !e.is_request() {
+        return false;
+    }
+
+    match std::error::Error::source(e).and_then(|s| s.downcast_ref::<hyper::Error>()) {
+        Some(e) => !e.is_user() && !e.is_parse(),
+        None => false,
+    }
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac to accept any key length");
+    mac.update(body);
+
+    let digest = mac.finalize().into_bytes();
+    let hex = digest
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!("sha256={}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use crate::events::{EventHandler, EventRequest};
+    use crate::git::{CommitEvent, GitEvent};
+    use crate::retry::RetryPolicy;
+
+    use super::{sign, WebhookHandler, WebhookOpts, SIGNATURE_HEADER};
+
+    type Requests = Arc<Mutex<Vec<(HashMap<String, String>, Vec<u8>)>>>;
+
+    /// A tiny HTTP server answering requests with `statuses` in order, and 200 after that. A
+    /// status of 0 closes the connection without answering.
+    async fn server(statuses: &[u16]) -> (String, Requests) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/webhook", listener.local_addr().unwrap());
+        let requests: Requests = Default::default();
+        let mut statuses = statuses.iter().copied().collect::<VecDeque<_>>();
+
+        let received = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+
+                let mut buf = Vec::new();
+                let (head, mut body) = loop {
+                    let mut chunk = [0; 4096];
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                        break (
+                            String::from_utf8_lossy(&buf[..end]).to_string(),
+                            buf[end + 4..].to_vec(),
+                        );
+                    }
+                };
+                let headers = head
+                    .lines()
+                    .skip(1)
+                    .filter_map(|l| l.split_once(": "))
+                    .map(|(k, v)| (k.to_lowercase(), v.to_string()))
+                    .collect::<HashMap<_, _>>();
+                let len = headers["content-length"].parse::<usize>().unwrap();
+                while body.len() < len {
+                    let mut chunk = [0; 4096];
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    body.extend_from_slice(&chunk[..n]);
+                }
+                received.lock().unwrap().push((headers, body));
+
+                let status = statuses.pop_front().unwrap_or(200);
+                if status == 0 {
+                    continue;
+                }
+                let res = format!(
+                    "HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(res.as_bytes()).await.unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn event() -> EventRequest {
+        EventRequest {
+            git: GitEvent::Commit(CommitEvent {
+                commit: "abc".into(),
+                reference: "refs/heads/main".into(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn opts() -> WebhookOpts {
+        let defaults = WebhookOpts::default();
+        WebhookOpts {
+            retry: RetryPolicy {
+                backoff: Duration::from_millis(1),
+                ..defaults.retry
+            },
+            ..defaults
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_and_signs() {
+        let (url, requests) = server(&[503, 429]).await;
+        let handler = WebhookHandler::with_opts(
+            url,
+            &WebhookOpts {
+                headers: vec![("authorization".into(), "Bearer token".into())],
+                secret: Some("secret".into()),
+                ..opts()
+            },
+        )
+        .unwrap();
+
+        handler.handle(event()).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        let (headers, body) = &requests[2];
+        assert_eq!(headers["authorization"], "Bearer token");
+        assert_eq!(headers["content-type"], "application/json");
+        assert_eq!(headers[SIGNATURE_HEADER], sign("secret", body));
+
+        let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(body["event"]["commit"], "abc");
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let (url, requests) = server(&[400]).await;
+        let handler = WebhookHandler::with_opts(url, &opts()).unwrap();
+
+        let err = handler.handle(event()).await.unwrap_err();
+
+        assert!(format!("{:?}", err).contains("400"));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_closed_connections_are_retried() {
+        let (url, requests) = server(&[0]).await;
+        let handler = WebhookHandler::with_opts(url, &opts()).unwrap();
+
+        handler.handle(event()).await.unwrap();
+
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests_are_not_retried() {
+        let (url, requests) = server(&[]).await;
+        let handler = WebhookHandler::with_opts(
+            url,
+            &WebhookOpts {
+                headers: vec![("invalid header".into(), "value".into())],
+                ..opts()
+            },
+        )
+        .unwrap();
+
+        let err = handler.handle(event()).await.unwrap_err();
+
+        assert!(format!("{:?}", err).contains("1 attempt"), "{:?}", err);
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let (url, requests) = server(&[500, 500, 500, 500]).await;
+        let handler = WebhookHandler::with_opts(url, &opts()).unwrap();
+
+        assert!(handler.handle(event()).await.is_err());
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+}
@@ -3,12 +3,20 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 
+pub mod persistent;
 pub mod volatile;
 
+/// Where repositories are cloned to.
+///
+/// Repositories are identified by their remote url, so one storage can be shared by many of them.
 #[async_trait]
 pub trait Storage {
-    async fn exists(&self) -> eyre::Result<Option<PathBuf>>;
-    async fn allocate(&self) -> eyre::Result<PathBuf>;
+    /// The directory of an earlier clone of `repo`, if there is one.
+    async fn exists(&self, repo: &str) -> eyre::Result<Option<PathBuf>>;
+    /// A directory to clone `repo` into.
+    async fn allocate(&self, repo: &str) -> eyre::Result<PathBuf>;
+    /// Removes the directory of `repo`, e.g. after a failed clone, so it's allocated again.
+    async fn release(&self, repo: &str) -> eyre::Result<()>;
 }
 
 pub type DynStorage = Arc<dyn Storage + Send + Sync>;
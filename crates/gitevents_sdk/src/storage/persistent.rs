@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use git2::Repository;
+
+use super::Storage;
+
+/// Keeps the clones of repositories in stable directories below `root`, so they're reused
+/// across restarts instead of being cloned again.
+///
+/// Each directory is named after the remote url, e.g. `github.com-kjuulh-gitevents-1b4e28ba` for
+/// `git@github.com:kjuulh/gitevents.git`. An existing directory has to be a clone of that url,
+/// anything else is an error rather than being overwritten. A clone which never finished, e.g.
+/// because the process died during it, is removed and cloned again.
+pub struct PersistentStorage {
+    root: PathBuf,
+}
+
+impl PersistentStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The directory `repo` is cloned to.
+    pub fn path(&self, repo: &str) -> PathBuf {
+        self.root.join(dir_name(repo))
+    }
+
+    /// Whether `path` is a finished clone of `repo`.
+    fn validate(path: &Path, repo: &str) -> eyre::Result<bool> {
+        let git = Repository::open(path).map_err(|e| {
+            eyre::eyre!(
+                "{} exists but isn't a git repository: {}",
+                path.display(),
+                e.message()
+            )
+        })?;
+        let remote = git.find_remote("origin")?;
+
+        match remote.url() {
+            Some(url) if url == repo => {}
+            url => eyre::bail!(
+                "{} is a clone of {}, expected {}",
+                path.display(),
+                url.unwrap_or("<none>"),
+                repo
+            ),
+        }
+
+        // HEAD is only born once the clone has fetched its refs
+        let finished = git.head().and_then(|head| head.peel_to_commit()).is_ok();
+        Ok(finished)
+    }
+}
+
+#[async_trait]
+impl Storage for PersistentStorage {
+    async fn exists(&self, repo: &str) -> eyre::Result<Option<PathBuf>> {
+        let path = self.path(repo);
+        let is_empty = match std::fs::read_dir(&path) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // Left behind by a clone which never started
+        if is_empty {
+            return Ok(None);
+        }
+
+        if !Self::validate(&path, repo)? {
+            tracing::warn!(
+                path = path.display().to_string(),
+                "removing unfinished clone"
+            );
+            self.release(repo).await?;
+            return Ok(None);
+        }
+        tracing::trace!(path = path.display().to_string(), "reusing clone");
+
+        Ok(Some(path))
+    }
+
+    async fn allocate(&self, repo: &str) -> eyre::Result<PathBuf> {
+        let path = self.path(repo);
+        std::fs::create_dir_all(&path)?;
+        tracing::trace!(path = path.display().to_string(), "allocating dir");
+
+        Ok(path)
+    }
+
+    async fn release(&self, repo: &str) -> eyre::Result<()> {
+        let path = self.path(repo);
+        match std::fs::remove_dir_all(&path) {
+            Ok(()) => {
+                tracing::trace!(path = path.display().to_string(), "releasing dir");
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// A readable directory name for a url, suffixed with a hash of the url, so urls which only
+/// differ in the characters that are replaced don't collide.
+fn dir_name(url: &str) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let without_user = without_scheme
+        .split_once('@')
+        .map_or(without_scheme, |(_, rest)| rest);
+    let readable = without_user.trim_end_matches('/').trim_end_matches(".git");
+
+    let mut name = String::new();
+    for c in readable.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            name.push(c);
+        } else if !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+
+    let hash = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, url.as_bytes());
+    format!(
+        "{}-{}",
+        name.trim_matches(['-', '.']),
+        &hash.simple().to_string()[..8]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use crate::storage::Storage;
+
+    use super::{dir_name, PersistentStorage};
+
+    #[test]
+    fn test_dir_names_are_readable_and_distinct() {
+        let ssh = dir_name("git@github.com:kjuulh/gitevents.git");
+        let https = dir_name("https://github.com/kjuulh/gitevents");
+
+        assert!(ssh.starts_with("github.com-kjuulh-gitevents-"), "{}", ssh);
+        assert!(
+            https.starts_with("github.com-kjuulh-gitevents-"),
+            "{}",
+            https
+        );
+        assert_ne!(ssh, https);
+        assert_eq!(ssh, dir_name("git@github.com:kjuulh/gitevents.git"));
+        assert!(!dir_name("../../etc").contains('/'));
+    }
+
+    #[tokio::test]
+    async fn test_reuses_clones_of_the_expected_remote() {
+        let mut root = temp_dir();
+        root.push(uuid::Uuid::new_v4().to_string());
+        let url = "https://example.com/repo.git";
+
+        let storage = PersistentStorage::new(&root);
+        assert!(storage.exists(url).await.unwrap().is_none());
+
+        let path = storage.allocate(url).await.unwrap();
+        assert!(storage.exists(url).await.unwrap().is_none());
+        let repo = git2::Repository::init(&path).unwrap();
+        repo.remote("origin", url).unwrap();
+        commit(&repo);
+
+        let storage = PersistentStorage::new(&root);
+        assert_eq!(storage.exists(url).await.unwrap(), Some(path.clone()));
+        assert!(storage
+            .exists("https://example.com/other.git")
+            .await
+            .unwrap()
+            .is_none());
+
+        repo.remote_set_url("origin", "https://example.com/other.git")
+            .unwrap();
+        let err = storage.exists(url).await.unwrap_err();
+        assert!(err.to_string().contains("other.git"), "{}", err);
+
+        storage.release(url).await.unwrap();
+        assert!(!path.exists());
+        assert!(storage.exists(url).await.unwrap().is_none());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_removes_unfinished_clones() {
+        let mut root = temp_dir();
+        root.push(uuid::Uuid::new_v4().to_string());
+        let url = "https://example.com/repo.git";
+
+        // What's left when the process dies after the remote was added, but before any ref was
+        // fetched
+        let storage = PersistentStorage::new(&root);
+        let path = storage.allocate(url).await.unwrap();
+        let repo = git2::Repository::init(&path).unwrap();
+        repo.remote("origin", url).unwrap();
+
+        assert!(storage.exists(url).await.unwrap().is_none());
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    fn commit(repo: &git2::Repository) {
+        let signature = git2::Signature::now("gitevents", "gitevents@example.com").unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[])
+            .unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::env::temp_dir;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -9,7 +10,14 @@ use super::Storage;
 
 pub struct InnerVolatileStorage {
     pub dir: PathBuf,
-    pub paths: Vec<PathBuf>,
+    /// The directory allocated for each repository.
+    pub paths: HashMap<String, PathBuf>,
+}
+
+impl Default for InnerVolatileStorage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InnerVolatileStorage {
@@ -28,21 +36,27 @@ impl InnerVolatileStorage {
         }
     }
 
-    pub async fn allocate(&mut self) -> eyre::Result<PathBuf> {
+    pub async fn allocate(&mut self, repo: &str) -> eyre::Result<PathBuf> {
         let mut new_dir = self.dir.clone();
         let new_dir_id = uuid::Uuid::new_v4();
         new_dir.push(new_dir_id.to_string());
 
         std::fs::create_dir_all(&new_dir)?;
-        self.paths.push(new_dir.clone());
+        if let Some(previous) = self.paths.insert(repo.to_string(), new_dir.clone()) {
+            std::fs::remove_dir_all(previous).ok();
+        }
 
-        tracing::trace!(new_dir = new_dir.display().to_string(), "allocating dir");
+        tracing::trace!(
+            repo = repo,
+            new_dir = new_dir.display().to_string(),
+            "allocating dir"
+        );
 
         Ok(new_dir)
     }
 
-    pub async fn exists(&self) -> eyre::Result<Option<PathBuf>> {
-        match self.paths.first() {
+    pub async fn exists(&self, repo: &str) -> eyre::Result<Option<PathBuf>> {
+        match self.paths.get(repo) {
             Some(path) => {
                 if path.exists() {
                     Ok(Some(path.clone()))
@@ -53,6 +67,21 @@ impl InnerVolatileStorage {
             None => Ok(None),
         }
     }
+
+    pub async fn release(&mut self, repo: &str) -> eyre::Result<()> {
+        if let Some(path) = self.paths.remove(repo) {
+            tracing::trace!(
+                repo = repo,
+                dir = path.display().to_string(),
+                "releasing dir"
+            );
+            if path.exists() {
+                std::fs::remove_dir_all(path)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Drop for InnerVolatileStorage {
@@ -66,6 +95,12 @@ pub struct VolatileStorage {
     pub inner: Arc<Mutex<InnerVolatileStorage>>,
 }
 
+impl Default for VolatileStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl VolatileStorage {
     pub fn new() -> Self {
         Self {
@@ -76,12 +111,16 @@ impl VolatileStorage {
 
 #[async_trait]
 impl Storage for VolatileStorage {
-    async fn allocate(&self) -> eyre::Result<PathBuf> {
-        self.inner.lock().await.allocate().await
+    async fn allocate(&self, repo: &str) -> eyre::Result<PathBuf> {
+        self.inner.lock().await.allocate(repo).await
     }
 
-    async fn exists(&self) -> eyre::Result<Option<PathBuf>> {
-        self.inner.lock().await.exists().await
+    async fn exists(&self, repo: &str) -> eyre::Result<Option<PathBuf>> {
+        self.inner.lock().await.exists(repo).await
+    }
+
+    async fn release(&self, repo: &str) -> eyre::Result<()> {
+        self.inner.lock().await.release(repo).await
     }
 }
 
@@ -106,13 +145,30 @@ mod test {
     #[traced_test]
     async fn test_volatile_storage_is_created_and_cleaned_up() {
         let storage = VolatileStorage::new();
-        storage.allocate().await.unwrap();
+        storage.allocate("repo").await.unwrap();
 
         let inner = storage.inner.lock().await;
 
         assert!(inner.dir.exists());
-        assert!(inner.paths.first().unwrap().exists());
+        assert!(inner.paths["repo"].exists());
 
         assert!(logs_contain("allocating dir"));
     }
+
+    #[tokio::test]
+    async fn test_volatile_storage_is_keyed_by_repo() {
+        let storage = VolatileStorage::new();
+
+        let a = storage.allocate("repo-a").await.unwrap();
+        let b = storage.allocate("repo-b").await.unwrap();
+        assert_ne!(a, b);
+        assert_eq!(storage.exists("repo-a").await.unwrap(), Some(a.clone()));
+        assert_eq!(storage.exists("repo-b").await.unwrap(), Some(b.clone()));
+        assert_eq!(storage.exists("repo-c").await.unwrap(), None);
+
+        storage.release("repo-a").await.unwrap();
+        assert!(!a.exists());
+        assert_eq!(storage.exists("repo-a").await.unwrap(), None);
+        assert!(b.exists());
+    }
 }